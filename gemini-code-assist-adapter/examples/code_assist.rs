@@ -1,6 +1,5 @@
-use gemini_code_assist_adapter::CodeAssistClient;
-use gemini_rust::{
-    Content, GenerateContentRequest, GenerationConfig, Role,
+use gemini_code_assist_adapter::{
+    CodeAssistClient, Content, GenerateContentRequest, GenerationConfig, Role,
 };
 use std::env;
 
@@ -10,17 +9,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let oauth_token = env::var("GCLOUD_ACCESS_TOKEN").expect("GCLOUD_ACCESS_TOKEN not set");
     let project_id = env::var("GCLOUD_PROJECT_ID").expect("GCLOUD_PROJECT_ID not set");
 
-    let mut client = CodeAssistClient::new(oauth_token, project_id)
-        .with_model("gemini-3-flash-preview");
-
-    if let Ok(proj) = client.load_code_assist().await {
-        client.set_project_id(proj);
-    }
-
-    if let Err(e) = client.onboard_user().await {
-        eprintln!("Onboarding warning: {}", e);
-        // Do not fail, try to continue, maybe already active
-    }
+    // Handshake + onboarding. Failures there are not fatal, maybe the user is already active
+    let client = CodeAssistClient::connect(oauth_token, Some(project_id), "gemini-3-flash-preview").await?;
 
     let request = GenerateContentRequest {
         contents: vec![
@@ -1,6 +1,5 @@
-use gemini_code_assist_adapter::CodeAssistClient;
-use gemini_rust::{
-    Content, GenerateContentRequest, GenerationConfig, Part, Role, Blob
+use gemini_code_assist_adapter::{
+    CodeAssistClient, Content, GenerateContentRequest, GenerationConfig, Part, Role, Blob
 };
 use std::env;
 use std::io::Write;
@@ -16,23 +15,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut client = CodeAssistClient::new(token, raw_project);
 
     // Handshake
-    match client.load_code_assist().await {
-        Ok(real_proj) => {
-            println!("🤝 Handshake success. Real Project: {}", real_proj);
-            client.set_project_id(real_proj);
+    match client.load_code_assist_full().await {
+        Ok(handshake) => {
+            println!("🤝 Handshake success. Real Project: {}", handshake.project_id);
+            // Onboard into the tier the server reports instead of assuming the free one
+            if let Some(tier) = handshake.onboarding_tier() {
+                client.set_tier_id(tier.id.clone());
+            }
+            client.set_project_id(handshake.project_id);
         },
         Err(e) => eprintln!("⚠️ Handshake warning: {}", e),
     }
 
     // Onboarding
-    if let Err(e) = client.onboard_user().await {
+    if let Err(e) = client.onboard_user_blocking().await {
         eprintln!("⚠️ Onboarding warning: {}", e);
     }
 
     // --- TEST 1: Image ---
     println!("\n🖼️  TEST 1: Multimodal (Image Analysis)...");
 
-    client = client.with_model("models/gemini-2.0-flash");
+    client = client.with_model("gemini-2.0-flash");
 
     let request_image = GenerateContentRequest {
         contents: vec![Content {
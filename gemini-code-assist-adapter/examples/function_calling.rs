@@ -0,0 +1,74 @@
+use gemini_code_assist_adapter::{
+    response, stream, CodeAssistClient, Content, FunctionCall, FunctionResponse, GenerateContentRequest, Role, Tool,
+};
+use serde_json::json;
+use std::env;
+
+/// Rounds of tool use before giving up, in case the model keeps calling.
+const MAX_ROUNDS: usize = 5;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let oauth_token = env::var("GCLOUD_ACCESS_TOKEN").expect("GCLOUD_ACCESS_TOKEN not set");
+    let project_id = env::var("GCLOUD_PROJECT_ID").expect("GCLOUD_PROJECT_ID not set");
+
+    let client = CodeAssistClient::connect(oauth_token, Some(project_id), "gemini-3-flash-preview").await?;
+
+    let tool: Tool = serde_json::from_value(json!({
+        "functionDeclarations": [{
+            "name": "get_weather",
+            "description": "Returns the current weather in a city.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "city": { "type": "string", "description": "City name, e.g. Berlin" }
+                },
+                "required": ["city"]
+            }
+        }]
+    }))?;
+
+    let mut history = vec![
+        Content::text("Should I take an umbrella in Berlin and in Lisbon today?").with_role(Role::User),
+    ];
+
+    for _ in 0..MAX_ROUNDS {
+        let request = GenerateContentRequest {
+            contents: history.clone(),
+            generation_config: None,
+            safety_settings: None,
+            tools: Some(vec![tool.clone()]),
+            tool_config: None,
+            system_instruction: None,
+            cached_content: None,
+        };
+
+        // Collecting the stream merges function call arguments split over several chunks
+        let answer = stream::collect_stream(client.generate_content_stream(&request).await?).await?;
+        let calls = response::extract_function_calls(&answer);
+        if calls.is_empty() {
+            println!("🤖 {}", answer.text());
+            return Ok(());
+        }
+
+        // The model turn must go back unchanged (thought signatures included)
+        history.extend(response::response_to_content(&answer));
+        let results = calls.iter().map(run_tool).collect::<Vec<_>>();
+        history.push(response::function_responses_content(results));
+    }
+
+    eprintln!("Gave up after {} rounds of tool calls", MAX_ROUNDS);
+    Ok(())
+}
+
+fn run_tool(call: &FunctionCall) -> FunctionResponse {
+    println!("🔧 {}({})", call.name, call.args);
+    match call.name.as_str() {
+        "get_weather" => {
+            let city = call.args["city"].as_str().unwrap_or_default();
+            let rain = city.eq_ignore_ascii_case("berlin");
+            FunctionResponse::new(&call.name, json!({ "city": city, "rain": rain, "temperature_c": 14 }))
+        }
+        _ => FunctionResponse::new(&call.name, json!({ "error": "unknown function" })),
+    }
+}
@@ -5,20 +5,17 @@ use gemini_code_assist_adapter::CodeAssistClient;
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let auth_manager = GoogleAuthManager::new();
 
-    println!("Opening browser for authentication...");
-    let token = auth_manager.login().await?;
-    println!("Successfully logged in!");
-
-    let projects = auth_manager.list_projects(&token).await?;
-
-    if projects.is_empty() {
-        return Err("No active Google Cloud projects found. Please create one.".into());
+    // The browser only opens if there is no valid cached token
+    if auth_manager.cached_token()?.is_none() {
+        println!("Opening browser for authentication...");
     }
 
-    let project_id = projects[0].clone();
-    println!("Using project: {}", project_id);
+    // Logs in and picks the remembered (or first active) project
+    let session = auth_manager.authenticate().await?;
+    println!("Successfully logged in!");
+    println!("token={}, project_id={}", session.access_token, session.project_id);
 
-    println!("token={token}, project_id={project_id}");
-    let client = CodeAssistClient::new(token, project_id);
+    let client = CodeAssistClient::from_session(session);
+    client.validate()?;
     Ok(())
 }
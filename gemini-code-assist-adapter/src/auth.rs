@@ -1,5 +1,9 @@
-use std::path::PathBuf;
-use yup_oauth2::{InstalledFlowAuthenticator, InstalledFlowReturnMethod, AccessToken};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use yup_oauth2::{InstalledFlowAuthenticator, InstalledFlowReturnMethod, AccessToken, ServiceAccountAuthenticator};
+use yup_oauth2::authenticator::DefaultAuthenticator;
 use yup_oauth2::authenticator_delegate::InstalledFlowDelegate;
 use serde::Deserialize;
 use reqwest::Client;
@@ -18,14 +22,148 @@ const SCOPES: &[&str] = &[
     "https://www.googleapis.com/auth/userinfo.email",
 ];
 
+// Retry settings for fetching a token without the browser (cached refresh token, service account)
+const TOKEN_MAX_ATTEMPTS: u32 = 3;
+const TOKEN_RETRY_BASE_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+
+const USERINFO_URL: &str = "https://www.googleapis.com/oauth2/v2/userinfo";
+const TOKENINFO_URL: &str = "https://oauth2.googleapis.com/tokeninfo";
+const SERVICE_USAGE_URL: &str = "https://serviceusage.googleapis.com/v1";
+
+// API that has to be enabled on a project for Code Assist to work
+const CODE_ASSIST_SERVICE: &str = "cloudaicompanion.googleapis.com";
+
+// File name prefix of per-account token caches, see `GoogleAuthManager::for_account`
+const ACCOUNT_CACHE_PREFIX: &str = "token_cache_";
+
+// Default window before expiry in which a token is considered "expiring soon"
+const DEFAULT_REFRESH_AHEAD: Duration = Duration::from_secs(120);
+
 #[derive(Clone, Debug)]
 pub struct AuthSession {
     pub access_token: String,
     pub project_id: String,
+    /// When the access token expires, if known.
+    pub expires_at: Option<SystemTime>,
+}
+
+/// Expiry state of a token, see [`GoogleAuthManager::expiry_status`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenExpiry {
+    /// Valid for longer than the refresh-ahead window.
+    Valid(Duration),
+    /// Expires within the refresh-ahead window, time to refresh.
+    ExpiringSoon(Duration),
+    Expired,
+    /// No expiry information available.
+    Unknown,
+}
+
+/// Lifecycle state of a Google Cloud project.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum ProjectLifecycle {
+    Active,
+    DeleteRequested,
+    DeleteInProgress,
+    /// Any state not covered above, kept verbatim.
+    Other(String),
+}
+
+impl From<String> for ProjectLifecycle {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "ACTIVE" => Self::Active,
+            "DELETE_REQUESTED" => Self::DeleteRequested,
+            "DELETE_IN_PROGRESS" => Self::DeleteInProgress,
+            _ => Self::Other(s),
+        }
+    }
+}
+
+/// A Google Cloud project as returned by [`GoogleAuthManager::list_projects_detailed`].
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProjectSummary {
+    #[serde(rename = "projectId")]
+    pub project_id: String,
+    /// Human-readable display name.
+    pub name: Option<String>,
+    #[serde(rename = "projectNumber")]
+    pub project_number: Option<String>,
+    #[serde(rename = "lifecycleState")]
+    pub state: ProjectLifecycle,
+}
+
+/// Result of a successful interactive login.
+#[derive(Clone, Debug)]
+pub struct LoginResult {
+    pub access_token: String,
+    /// Scopes the user actually granted on the consent screen.
+    pub granted_scopes: Vec<String>,
+    /// When the access token expires, if known.
+    pub expires_at: Option<SystemTime>,
+}
+
+/// Information about an access token, see [`GoogleAuthManager::introspect_token`].
+#[derive(Clone, Debug)]
+pub struct TokenInfo {
+    pub scopes: Vec<String>,
+    /// Remaining lifetime of the token at the time of the lookup.
+    pub expires_in: Option<Duration>,
+    /// Email of the account the token belongs to (needs the `userinfo.email` scope).
+    pub email: Option<String>,
+    /// OAuth client the token was issued to.
+    pub audience: Option<String>,
+}
+
+/// Raw response of Google's `tokeninfo` endpoint.
+#[derive(Deserialize)]
+struct TokenInfoResponse {
+    #[serde(default)]
+    scope: String,
+    // Sent as a string, e.g. "3599"
+    expires_in: Option<String>,
+    email: Option<String>,
+    aud: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UserInfoResponse {
+    email: Option<String>,
+}
+
+/// Error body of the `tokeninfo` endpoint.
+#[derive(Deserialize)]
+struct TokenInfoError {
+    error_description: Option<String>,
+    error: Option<String>,
+}
+
+/// Where obtained tokens are kept.
+enum TokenStore {
+    /// Persisted to the cache file.
+    Disk,
+    /// Kept for the process lifetime only, inside the (reused) authenticator.
+    Memory(Mutex<Option<DefaultAuthenticator>>),
+    /// Obtained with a service account key, no interactive login involved.
+    ServiceAccount(DefaultAuthenticator),
+}
+
+/// Entry of the yup-oauth2 token cache file.
+#[derive(Deserialize)]
+struct CachedToken {
+    scopes: Vec<String>,
+    token: yup_oauth2::storage::TokenInfo,
 }
 
 pub struct GoogleAuthManager {
     _cache_path: PathBuf,
+    store: TokenStore,
+    refresh_ahead: Duration,
+    /// Fixed loopback port for the OAuth redirect, `None` picks a random one.
+    redirect_port: Option<u16>,
+    /// Account email per access token, see [`Self::user_email`].
+    email_cache: Mutex<HashMap<String, String>>,
 }
 
 struct BrowserFlowDelegate;
@@ -47,65 +185,520 @@ impl InstalledFlowDelegate for BrowserFlowDelegate {
     }
 }
 
+impl Default for GoogleAuthManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl GoogleAuthManager {
     pub fn new() -> Self {
-        // Determine the path for storing the token:
-        let proj_dirs = directories::ProjectDirs::from("com", "gemini-gui", "app")
-            .expect("Could not determine config directory");
-
-        let cache_dir = proj_dirs.config_dir();
-        std::fs::create_dir_all(cache_dir).ok();
+        let cache_dir = Self::cache_dir();
+        std::fs::create_dir_all(&cache_dir).ok();
 
         Self {
             _cache_path: cache_dir.join("token_cache.json"),
+            store: TokenStore::Disk,
+            refresh_ahead: DEFAULT_REFRESH_AHEAD,
+            redirect_port: None,
+            email_cache: Mutex::new(HashMap::new()),
         }
     }
 
-    /// Starts the login process.
-    /// 1. Opens the browser.
-    /// 2. User logs in to Google.
-    /// 3. Returns the Access Token.
-    pub async fn login(&self) -> Result<String, AdapterError> {
+    /// Creates a manager with its own token cache (`token_cache_<account>.json`) and remembered
+    /// project, so several Google accounts can be logged in side by side. Characters that
+    /// aren't safe in a file name are replaced with `_`, so any label (e.g. an email) works.
+    pub fn for_account(account: &str) -> Self {
+        let account: String = account
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '@') { c } else { '_' })
+            .collect();
+        let path = Self::cache_dir().join(format!("{}{}.json", ACCOUNT_CACHE_PREFIX, account));
+        Self::new().with_cache_path(path)
+    }
+
+    /// Names of the accounts created with [`Self::for_account`] that have a token cache.
+    pub fn list_accounts() -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(Self::cache_dir()) else {
+            return Vec::new();
+        };
+
+        let mut accounts: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let account = name.strip_prefix(ACCOUNT_CACHE_PREFIX)?.strip_suffix(".json")?;
+                Some(account.to_string())
+            })
+            .collect();
+        accounts.sort();
+        accounts
+    }
+
+    /// Same as [`Self::for_account`], under the name used by account switchers.
+    pub fn with_account(account_label: &str) -> Self {
+        Self::for_account(account_label)
+    }
+
+    /// Same as [`Self::list_accounts`], under the name used by account switchers.
+    pub fn list_cached_accounts() -> Vec<String> {
+        Self::list_accounts()
+    }
+
+    /// Directory for the token caches. Some sandboxed environments have no
+    /// config directory, fall back to the temp dir there.
+    fn cache_dir() -> PathBuf {
+        match directories::ProjectDirs::from("com", "gemini-gui", "app") {
+            Some(proj_dirs) => proj_dirs.config_dir().to_path_buf(),
+            None => {
+                let dir = std::env::temp_dir().join("gemini-gui");
+                log::warn!("Could not determine config directory, storing tokens in {:?}", dir);
+                dir
+            }
+        }
+    }
+
+    /// Creates a manager that never writes credentials to disk. Tokens live in memory for the
+    /// lifetime of the manager, so the user has to log in again after a restart.
+    pub fn in_memory() -> Self {
+        Self {
+            _cache_path: PathBuf::new(),
+            store: TokenStore::Memory(Mutex::new(None)),
+            refresh_ahead: DEFAULT_REFRESH_AHEAD,
+            redirect_port: None,
+            email_cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a manager that authenticates with a service account JSON key instead of the
+    /// browser flow, for headless servers and CI. [`Self::login`] then returns a token right away.
+    ///
+    /// The service account is granted the `cloud-platform` and `userinfo.email` scopes; it needs
+    /// the "Gemini for Google Cloud User" role (`roles/cloudaicompanion.user`) on the project.
+    pub async fn from_service_account(path: &Path) -> Result<Self, AdapterError> {
+        let key = yup_oauth2::read_service_account_key(path).await?;
+        let auth = ServiceAccountAuthenticator::builder(key).build().await?;
+
+        Ok(Self {
+            _cache_path: PathBuf::new(),
+            store: TokenStore::ServiceAccount(auth),
+            refresh_ahead: DEFAULT_REFRESH_AHEAD,
+            redirect_port: None,
+            email_cache: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Overrides where the token cache file is stored.
+    pub fn with_cache_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self._cache_path = path.into();
+        if let Some(parent) = self._cache_path.parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
+        self
+    }
+
+    /// Pins the local OAuth callback server to `port` instead of a random one, for networks
+    /// where only specific loopback ports are allowed. The port must not be in use during login.
+    pub fn with_redirect_port(mut self, port: u16) -> Self {
+        self.redirect_port = Some(port);
+        self
+    }
+
+    /// Sets how long before expiry a token counts as [`TokenExpiry::ExpiringSoon`] (2 minutes by default).
+    pub fn with_refresh_ahead(mut self, refresh_ahead: Duration) -> Self {
+        self.refresh_ahead = refresh_ahead;
+        self
+    }
+
+    /// Time left until the session's token expires. `None` if the expiry is unknown,
+    /// zero if it already expired.
+    pub fn time_until_expiry(session: &AuthSession) -> Option<Duration> {
+        let expires_at = session.expires_at?;
+        Some(expires_at.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO))
+    }
+
+    /// Classifies the session's token against the refresh-ahead window.
+    pub fn expiry_status(&self, session: &AuthSession) -> TokenExpiry {
+        match Self::time_until_expiry(session) {
+            None => TokenExpiry::Unknown,
+            Some(left) if left.is_zero() => TokenExpiry::Expired,
+            Some(left) if left <= self.refresh_ahead => TokenExpiry::ExpiringSoon(left),
+            Some(left) => TokenExpiry::Valid(left),
+        }
+    }
+
+    /// Refreshes the session's token if it is expired or about to expire.
+    /// Returns `true` if the token was refreshed. Uses the cached refresh token, so
+    /// no browser interaction is needed unless the cache is gone.
+    pub async fn refresh_if_needed(&self, session: &mut AuthSession) -> Result<bool, AdapterError> {
+        if matches!(self.expiry_status(session), TokenExpiry::Valid(_) | TokenExpiry::Unknown) {
+            return Ok(false);
+        }
+
+        log::debug!("Access token expires soon, refreshing ahead of time");
+        self.refresh(session).await?;
+        Ok(true)
+    }
+
+    /// Unconditionally fetches a new access token for the session using the cached refresh token.
+    pub async fn refresh(&self, session: &mut AuthSession) -> Result<(), AdapterError> {
+        let token = self.authenticator().await?.force_refreshed_token(SCOPES).await?;
+        session.access_token = token.token().map(|s| s.to_string()).unwrap_or_default();
+        session.expires_at = token.expiration_time().map(SystemTime::from);
+        Ok(())
+    }
+
+    /// Builds the installed-flow authenticator backed by the token cache.
+    async fn authenticator(&self) -> Result<DefaultAuthenticator, AdapterError> {
+        let reused = match &self.store {
+            TokenStore::Memory(cached) => cached.lock().unwrap().clone(),
+            TokenStore::ServiceAccount(auth) => Some(auth.clone()),
+            TokenStore::Disk => None,
+        };
+        if let Some(auth) = reused {
+            return Ok(auth);
+        }
+
         let secret = yup_oauth2::ApplicationSecret {
             client_id: OAUTH_CLIENT_ID.to_string(),
             client_secret: OAUTH_CLIENT_SECRET.to_string(),
             token_uri: "https://oauth2.googleapis.com/token".to_string(),
             auth_uri: "https://accounts.google.com/o/oauth2/auth".to_string(),
-            redirect_uris: vec!["http://localhost".to_string()], 
+            redirect_uris: vec![match self.redirect_port {
+                Some(port) => format!("http://localhost:{}", port),
+                None => "http://localhost".to_string(),
+            }],
             ..Default::default()
         };
 
+        // Will start a local server
+        let return_method = match self.redirect_port {
+            Some(port) => {
+                log::debug!("OAuth callback server will listen on port {}", port);
+                InstalledFlowReturnMethod::HTTPPortRedirect(port)
+            }
+            None => InstalledFlowReturnMethod::HTTPRedirect,
+        };
+
         // Create the authenticator
-        let auth = InstalledFlowAuthenticator::builder(
-            secret,
-            InstalledFlowReturnMethod::HTTPRedirect, // Will start a local server
-        )
-        .persist_tokens_to_disk(&self._cache_path)
-        .flow_delegate(Box::new(BrowserFlowDelegate))
-        .build()
-        .await
-        .map_err(|e| AdapterError::StreamError(format!("Auth builder failed: {}", e)))?;
-
-        // Obtain the token
-        let token: AccessToken = auth
-            .token(SCOPES)
-            .await
-            .map_err(|e| AdapterError::StreamError(format!("Failed to get token: {}", e)))?;
-
-        Ok(token.token().map(|s| s.to_string()).unwrap_or_default())
-    }
-
-    /// Clears the token cache file from disk.
+        let builder = InstalledFlowAuthenticator::builder(secret, return_method)
+            .flow_delegate(Box::new(BrowserFlowDelegate));
+        let auth = match &self.store {
+            TokenStore::Disk => builder.persist_tokens_to_disk(&self._cache_path).build().await?,
+            TokenStore::Memory(cached) => {
+                // Without a disk cache the tokens only live inside this authenticator, keep it
+                let auth = builder.build().await?;
+                *cached.lock().unwrap() = Some(auth.clone());
+                auth
+            }
+            TokenStore::ServiceAccount(_) => unreachable!("the service account authenticator is always reused"),
+        };
+
+        Ok(auth)
+    }
+
+    /// Starts the login process.
+    /// 1. Opens the browser.
+    /// 2. User logs in to Google.
+    /// 3. Returns the Access Token.
+    ///
+    /// Fails with [`AdapterError::AuthError`] if no token could be obtained or the user declined
+    /// any of the required scopes.
+    pub async fn login(&self) -> Result<String, AdapterError> {
+        Ok(self.login_with_scopes().await?.access_token)
+    }
+
+    /// Same as [`Self::login`], but returns a session with the token's expiry, ready for
+    /// [`Self::refresh_if_needed`]. The project is the remembered one, if any.
+    pub async fn login_with_expiry(&self) -> Result<AuthSession, AdapterError> {
+        let login = self.login_with_scopes().await?;
+        Ok(AuthSession {
+            access_token: login.access_token,
+            project_id: self.remembered_project().unwrap_or_default(),
+            expires_at: login.expires_at,
+        })
+    }
+
+    /// Logs in and resolves the project in one go: the remembered project, or else the first
+    /// active one of the account. Fails with [`AdapterError::NoProject`] if there is none.
+    pub async fn authenticate(&self) -> Result<AuthSession, AdapterError> {
+        let mut session = self.login_with_expiry().await?;
+        if session.project_id.is_empty() {
+            session.project_id = self.list_projects(&session.access_token).await?
+                .into_iter()
+                .next()
+                .ok_or(AdapterError::NoProject)?;
+        }
+        Ok(session)
+    }
+
+    /// Same as [`Self::login`], but also returns the scopes granted by the user.
+    ///
+    /// Transient failures are retried with backoff only when the token can be obtained without
+    /// the browser (a cached refresh token or a service account). A fresh interactive login is
+    /// not retried: the browser redirect and the code exchange happen inside one call of the
+    /// OAuth library, so a retry would send the user through the consent screen again.
+    /// Failures are reported as [`AdapterError::AuthError`].
+    pub async fn login_with_scopes(&self) -> Result<LoginResult, AdapterError> {
+        // A token is only persisted after a successful code exchange, so retrying a fresh login
+        // would open the browser again. Only retry when the token can be obtained without it.
+        let max_attempts = if self.can_refresh_silently() { TOKEN_MAX_ATTEMPTS } else { 1 };
+        let auth = self.authenticator().await?;
+
+        // Obtain the token. Transient (network) failures are retried with backoff.
+        let mut attempt = 1;
+        let token: AccessToken = loop {
+            match auth.token(SCOPES).await {
+                Ok(token) => break token,
+                Err(e) => {
+                    let transient = !matches!(e, yup_oauth2::Error::AuthError(_) | yup_oauth2::Error::UserError(_));
+                    if !transient || attempt >= max_attempts {
+                        return Err(AdapterError::AuthError(format!("Failed to obtain an access token: {}", e)));
+                    }
+
+                    let delay = TOKEN_RETRY_BASE_DELAY * 2u32.pow(attempt - 1);
+                    log::warn!("Token exchange failed (attempt {}/{}): {}. Retrying in {:?}", attempt, max_attempts, e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+            }
+        };
+
+        let access_token = token.token().map(|s| s.to_string()).unwrap_or_default();
+        let granted_scopes = Self::fetch_token_info(&access_token).await?.scopes;
+
+        let missing: Vec<&str> = SCOPES
+            .iter()
+            .copied()
+            .filter(|s| !granted_scopes.iter().any(|g| g == s))
+            .collect();
+        if !missing.is_empty() {
+            return Err(AdapterError::AuthError(format!(
+                "The following permissions were declined on the consent screen: {}. Please log in again and grant all requested permissions.",
+                missing.join(", ")
+            )));
+        }
+
+        Ok(LoginResult {
+            access_token,
+            granted_scopes,
+            expires_at: token.expiration_time().map(SystemTime::from),
+        })
+    }
+
+    /// Looks up a token with Google's `tokeninfo` endpoint without calling the Code Assist API.
+    ///
+    /// Useful to show the logged-in account or to catch a wrong-scope / almost-expired token
+    /// before it fails a request. An invalid or expired token yields [`AdapterError::AuthError`].
+    pub async fn introspect_token(&self, access_token: &str) -> Result<TokenInfo, AdapterError> {
+        Self::fetch_token_info(access_token).await
+    }
+
+    /// Returns the email of the account the token belongs to, e.g. for a "Signed in as" label.
+    /// The result is cached per token, so repeated calls don't hit the network.
+    pub async fn user_email(&self, access_token: &str) -> Result<String, AdapterError> {
+        if let Some(email) = self.email_cache.lock().unwrap().get(access_token) {
+            return Ok(email.clone());
+        }
+
+        let response = Client::new()
+            .get(USERINFO_URL)
+            .bearer_auth(access_token)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(AdapterError::AuthError(format!(
+                "Failed to fetch user info: {}",
+                response.text().await.unwrap_or_default()
+            )));
+        }
+
+        let email = response.json::<UserInfoResponse>().await?
+            .email
+            .ok_or_else(|| AdapterError::AuthError("User info contains no email, was the userinfo.email scope granted?".into()))?;
+
+        self.email_cache.lock().unwrap().insert(access_token.to_string(), email.clone());
+        Ok(email)
+    }
+
+    async fn fetch_token_info(access_token: &str) -> Result<TokenInfo, AdapterError> {
+        let response = Client::new()
+            .get(TOKENINFO_URL)
+            .query(&[("access_token", access_token)])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let reason = serde_json::from_str::<TokenInfoError>(&body)
+                .ok()
+                .and_then(|e| e.error_description.or(e.error))
+                .unwrap_or(body);
+            return Err(AdapterError::AuthError(format!("Invalid access token: {}", reason)));
+        }
+
+        let info: TokenInfoResponse = response.json().await?;
+        Ok(TokenInfo {
+            scopes: info.scope.split_whitespace().map(str::to_string).collect(),
+            expires_in: info.expires_in
+                .and_then(|s| s.parse().ok())
+                .map(Duration::from_secs),
+            email: info.email,
+            audience: info.aud,
+        })
+    }
+
+    /// Whether a token can be obtained without the browser: with a service account, or with a
+    /// refresh token in the cache file. In-memory managers are never sure to have one.
+    fn can_refresh_silently(&self) -> bool {
+        match &self.store {
+            TokenStore::ServiceAccount(_) => true,
+            TokenStore::Memory(_) => false,
+            TokenStore::Disk => std::fs::read_to_string(&self._cache_path)
+                .ok()
+                .and_then(|data| serde_json::from_str::<Vec<CachedToken>>(&data).ok())
+                .unwrap_or_default()
+                .iter()
+                .filter(|t| SCOPES.iter().all(|scope| t.scopes.iter().any(|s| s == scope)))
+                .any(|t| t.token.refresh_token.is_some()),
+        }
+    }
+
+    /// Returns the cached access token if it is still valid, without opening the browser or
+    /// any network call. `None` if nothing is cached or the token has expired (in which case
+    /// [`Self::login`] can usually refresh it silently). Always `None` without a cache file.
+    pub fn cached_token(&self) -> Result<Option<String>, AdapterError> {
+        if !matches!(self.store, TokenStore::Disk) {
+            return Ok(None);
+        }
+
+        let data = match std::fs::read_to_string(&self._cache_path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let tokens: Vec<CachedToken> = serde_json::from_str(&data)?;
+
+        Ok(tokens
+            .into_iter()
+            .filter(|t| SCOPES.iter().all(|scope| t.scopes.iter().any(|s| s == scope)))
+            .filter(|t| !t.token.is_expired())
+            .find_map(|t| t.token.access_token))
+    }
+
+    /// Clears the token cache file from disk (or the in-memory tokens, see [`Self::in_memory`]).
     pub fn clear_token_cache(&self) {
+        match &self.store {
+            TokenStore::Memory(cached) => {
+                cached.lock().unwrap().take();
+                self.email_cache.lock().unwrap().clear();
+                log::info!("In-memory token cache cleared");
+                return;
+            }
+            // The key is all there is, a new token is obtained on demand
+            TokenStore::ServiceAccount(_) => {
+                self.email_cache.lock().unwrap().clear();
+                return;
+            }
+            TokenStore::Disk => {}
+        }
+
         if self._cache_path.exists() {
             let _ = std::fs::remove_file(&self._cache_path);
             log::info!("Token cache cleared: {:?}", self._cache_path);
         }
     }
 
+    /// Stores the project selected for this manager's account next to its token cache.
+    pub fn remember_project(&self, project_id: &str) -> std::io::Result<()> {
+        match self.project_path() {
+            Some(path) => std::fs::write(path, project_id),
+            None => Ok(()),
+        }
+    }
+
+    /// Project stored by [`Self::remember_project`], if any.
+    pub fn remembered_project(&self) -> Option<String> {
+        let project = std::fs::read_to_string(self.project_path()?).ok()?;
+        Some(project.trim().to_string()).filter(|p| !p.is_empty())
+    }
+
+    /// `token_cache_work.json` -> `token_cache_work.project`. None without a cache file.
+    fn project_path(&self) -> Option<PathBuf> {
+        match self.store {
+            TokenStore::Disk => Some(self._cache_path.with_extension("project")),
+            TokenStore::Memory(_) | TokenStore::ServiceAccount(_) => None,
+        }
+    }
+
     ///  finds the list of Google Cloud projects available to the user.
     /// This is Automaticallyneeded so the user can select a project_id.
+    /// Only active projects are returned, see [`Self::list_projects_detailed`] for all of them.
     pub async fn list_projects(&self, access_token: &str) -> Result<Vec<String>, AdapterError> {
+        Self::list_projects_with_token(access_token).await
+    }
+
+    /// Same as [`Self::list_projects`], but doesn't need a manager (and thus no token cache dir).
+    /// Handy with a token obtained elsewhere, e.g. from `gcloud auth print-access-token`.
+    pub async fn list_projects_with_token(access_token: &str) -> Result<Vec<String>, AdapterError> {
+        let active_projects = Self::list_projects_detailed_with_token(access_token)
+            .await?
+            .into_iter()
+            .filter(|p| p.state == ProjectLifecycle::Active)
+            .map(|p| p.project_id)
+            .collect();
+
+        Ok(active_projects)
+    }
+
+    /// Same as [`Self::list_projects`], but only returns projects with the Gemini for Google Cloud
+    /// API (`cloudaicompanion.googleapis.com`) enabled, checked via the Service Usage API.
+    /// Projects whose API state can't be read (e.g. missing permission) are left out.
+    pub async fn list_eligible_projects(&self, access_token: &str) -> Result<Vec<String>, AdapterError> {
+        let projects = Self::list_projects_with_token(access_token).await?;
+        let client = Client::new();
+
+        let checks = projects.into_iter().map(|project| {
+            let client = &client;
+            async move {
+                match Self::service_enabled(client, access_token, &project).await {
+                    Ok(true) => Some(project),
+                    Ok(false) => None,
+                    Err(e) => {
+                        log::debug!("Could not check the API state of project {}: {}", project, e);
+                        None
+                    }
+                }
+            }
+        });
+
+        Ok(futures::future::join_all(checks).await.into_iter().flatten().collect())
+    }
+
+    async fn service_enabled(client: &Client, access_token: &str, project_id: &str) -> Result<bool, AdapterError> {
+        #[derive(Deserialize)]
+        struct ServiceState {
+            state: Option<String>,
+        }
+
+        let url = format!("{}/projects/{}/services/{}", SERVICE_USAGE_URL, project_id, CODE_ASSIST_SERVICE);
+        let response = client.get(&url).bearer_auth(access_token).send().await?;
+        if !response.status().is_success() {
+            return Err(AdapterError::from_response(response).await);
+        }
+
+        let service: ServiceState = response.json().await?;
+        Ok(service.state.as_deref() == Some("ENABLED"))
+    }
+
+    /// Lists all projects visible to the user, including the ones pending deletion.
+    pub async fn list_projects_detailed(&self, access_token: &str) -> Result<Vec<ProjectSummary>, AdapterError> {
+        Self::list_projects_detailed_with_token(access_token).await
+    }
+
+    /// Same as [`Self::list_projects_detailed`], without a manager.
+    pub async fn list_projects_detailed_with_token(access_token: &str) -> Result<Vec<ProjectSummary>, AdapterError> {
         let client = Client::new();
         let url = "https://cloudresourcemanager.googleapis.com/v1/projects";
 
@@ -116,34 +709,28 @@ impl GoogleAuthManager {
             .await?;
 
         if !response.status().is_success() {
-            return Err(AdapterError::ApiError {
-                code: response.status().as_u16(),
-                message: "Failed to list projects".to_string()
-            });
-        }
-
-        #[derive(Deserialize)]
-        struct Project {
-            #[serde(rename = "projectId")]
-            project_id: String,
-            #[serde(rename = "lifecycleState")]
-            state: String,
+            return Err(AdapterError::from_response(response).await.context("Failed to list projects"));
         }
 
         #[derive(Deserialize)]
         struct ProjectsResponse {
-            projects: Option<Vec<Project>>,
+            projects: Option<Vec<ProjectSummary>>,
         }
 
         let resp: ProjectsResponse = response.json().await?;
+        Ok(resp.projects.unwrap_or_default())
+    }
+}
 
-        let active_projects = resp.projects
-            .unwrap_or_default()
-            .into_iter()
-            .filter(|p| p.state == "ACTIVE")
-            .map(|p| p.project_id)
-            .collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        Ok(active_projects)
+    #[test]
+    fn account_labels_become_safe_file_names() {
+        let manager = GoogleAuthManager::with_account("jane.doe@example.com/../work profile");
+        let file_name = manager._cache_path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(file_name, "token_cache_jane.doe@example.com_.._work_profile.json");
+        assert_eq!(manager._cache_path.parent(), Some(GoogleAuthManager::cache_dir().as_path()));
     }
 }
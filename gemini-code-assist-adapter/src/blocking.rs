@@ -0,0 +1,95 @@
+//! Synchronous wrapper around [`crate::CodeAssistClient`].
+//!
+//! The client drives the async implementation on its own single-threaded tokio runtime,
+//! so it must not be used from inside another async runtime (it would panic, same as `reqwest::blocking`).
+
+use std::sync::Arc;
+use futures::StreamExt;
+use gemini_rust::{GenerateContentRequest, GenerationResponse};
+use tokio::runtime::Runtime;
+use crate::error::AdapterError;
+use crate::ResponseStream;
+
+/// Blocking version of [`crate::CodeAssistClient`].
+#[derive(Clone)]
+pub struct CodeAssistClient {
+    inner: crate::CodeAssistClient,
+    runtime: Arc<Runtime>,
+}
+
+impl CodeAssistClient {
+    /// Creates a new blocking client. See [`crate::CodeAssistClient::new`].
+    pub fn new(auth_token: String, project_id: String) -> Result<Self, AdapterError> {
+        Self::from_async(crate::CodeAssistClient::new(auth_token, project_id))
+    }
+
+    /// Wraps an already configured async client.
+    pub fn from_async(inner: crate::CodeAssistClient) -> Result<Self, AdapterError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        Ok(Self { inner, runtime: Arc::new(runtime) })
+    }
+
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.inner = self.inner.with_model(model);
+        self
+    }
+
+    /// Gives access to the underlying async client.
+    pub fn inner(&self) -> &crate::CodeAssistClient {
+        &self.inner
+    }
+
+    /// Blocking version of [`crate::CodeAssistClient::load_code_assist`]. Returns the effective project.
+    pub fn load_code_assist(&self) -> Result<String, AdapterError> {
+        self.runtime.block_on(self.inner.load_code_assist())
+    }
+
+    /// Onboards the user and waits until it completes,
+    /// see [`crate::CodeAssistClient::onboard_user_blocking`].
+    pub fn onboard_user(&mut self) -> Result<(), AdapterError> {
+        self.runtime.block_on(self.inner.onboard_user_blocking())
+    }
+
+    /// See [`crate::CodeAssistClient::set_project_id`].
+    pub fn set_project_id(&self, project_id: String) {
+        self.inner.set_project_id(project_id);
+    }
+
+    /// Blocking version of [`crate::CodeAssistClient::generate_content`].
+    pub fn generate_content(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<GenerationResponse, AdapterError> {
+        self.runtime.block_on(self.inner.generate_content(request))
+    }
+
+    /// Blocking version of [`crate::CodeAssistClient::generate_content_stream`].
+    /// Each call to `next()` blocks until the next chunk arrives.
+    pub fn generate_content_stream(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<ResponseIter, AdapterError> {
+        let stream = self.runtime.block_on(self.inner.generate_content_stream(request))?;
+        Ok(ResponseIter {
+            stream,
+            runtime: self.runtime.clone(),
+        })
+    }
+}
+
+/// Iterator over streamed response chunks.
+pub struct ResponseIter {
+    stream: ResponseStream,
+    runtime: Arc<Runtime>,
+}
+
+impl Iterator for ResponseIter {
+    type Item = Result<GenerationResponse, AdapterError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
@@ -0,0 +1,95 @@
+//! Validating builder for `CodeAssistClient`.
+
+use crate::error::AdapterError;
+use crate::retry::RetryPolicy;
+use crate::CodeAssistClient;
+
+/// Builds a [`CodeAssistClient`] and checks the configuration before the first request.
+#[derive(Debug, Clone, Default)]
+pub struct CodeAssistClientBuilder {
+    auth_token: String,
+    project_id: Option<String>,
+    model: Option<String>,
+    base_url: Option<String>,
+    http_client: Option<reqwest::Client>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl CodeAssistClientBuilder {
+    pub fn new(auth_token: impl Into<String>) -> Self {
+        Self {
+            auth_token: auth_token.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Without a project the handshake has to provide one (managed projects of the free tier).
+    pub fn project_id(mut self, project_id: impl Into<String>) -> Self {
+        self.project_id = Some(project_id.into());
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// See [`CodeAssistClient::with_base_url`].
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
+
+    /// Uses a preconfigured HTTP client, e.g. with a proxy or custom timeouts.
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Fails with [`AdapterError::InvalidConfig`] on an empty token, a malformed project id,
+    /// or anything else [`CodeAssistClient::validate`] rejects.
+    pub fn build(self) -> Result<CodeAssistClient, AdapterError> {
+        match &self.project_id {
+            Some(project_id) if !is_valid_project_id(project_id) => {
+                return Err(AdapterError::InvalidConfig(format!(
+                    "{:?} is not a valid project id (6-30 lowercase letters, digits or hyphens, starting with a letter)",
+                    project_id
+                )));
+            }
+            _ => {}
+        }
+
+        let mut client = CodeAssistClient::new(self.auth_token, self.project_id.unwrap_or_default());
+        if let Some(model) = self.model {
+            client = client.with_model(model);
+        }
+        if let Some(url) = self.base_url {
+            client = client.with_base_url(url);
+        }
+        if let Some(http_client) = self.http_client {
+            client = client.with_http_client(http_client);
+        }
+        if let Some(policy) = self.retry_policy {
+            client = client.with_retry_policy(policy);
+        }
+
+        client.validate()?;
+        Ok(client)
+    }
+}
+
+/// Checks the Cloud project id format `[a-z][a-z0-9-]{4,28}[a-z0-9]`.
+fn is_valid_project_id(id: &str) -> bool {
+    let bytes = id.as_bytes();
+    let (Some(first), Some(last)) = (bytes.first(), bytes.last()) else { return false };
+
+    (6..=30).contains(&bytes.len())
+        && first.is_ascii_lowercase()
+        && (last.is_ascii_lowercase() || last.is_ascii_digit())
+        && bytes.iter().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || *b == b'-')
+}
@@ -0,0 +1,83 @@
+//! Circuit breaker that stops sending requests while the backend keeps failing.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::error::AdapterError;
+
+#[derive(Debug)]
+enum State {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// Cooldown is over and a single trial request is in flight.
+    HalfOpen { since: Instant },
+}
+
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Returns [`AdapterError::CircuitOpen`] if requests are currently short-circuited.
+    pub(crate) fn check(&self) -> Result<(), AdapterError> {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } => {
+                let now = Instant::now();
+                if now < until {
+                    return Err(AdapterError::CircuitOpen { retry_after: until - now });
+                }
+                log::debug!("Circuit breaker cooldown over, allowing a trial request");
+                *state = State::HalfOpen { since: now };
+                Ok(())
+            }
+            // Only the trial request may pass until its outcome is known. If it never reports
+            // back (e.g. the future was dropped), allow another trial after one more cooldown.
+            State::HalfOpen { since } => {
+                let now = Instant::now();
+                if now < since + self.cooldown {
+                    return Err(AdapterError::CircuitOpen { retry_after: since + self.cooldown - now });
+                }
+                *state = State::HalfOpen { since: now };
+                Ok(())
+            }
+        }
+    }
+
+    /// Records the outcome of a request that passed [`Self::check`].
+    pub(crate) fn record<T>(&self, result: &Result<T, AdapterError>) {
+        let mut state = self.state.lock().unwrap();
+        let failed = matches!(result, Err(e) if is_backend_failure(e));
+
+        *state = match (&*state, failed) {
+            (_, false) => State::Closed { failures: 0 },
+            (State::Closed { failures }, true) if failures + 1 < self.threshold => {
+                State::Closed { failures: failures + 1 }
+            }
+            _ => {
+                log::warn!("Backend keeps failing, pausing requests for {:?}", self.cooldown);
+                State::Open { until: Instant::now() + self.cooldown }
+            }
+        };
+    }
+}
+
+/// Failures that indicate a backend problem (as opposed to a bad request).
+fn is_backend_failure(error: &AdapterError) -> bool {
+    match error {
+        AdapterError::RequestFailed(_) | AdapterError::StreamError(_) => true,
+        AdapterError::ApiError { code, .. } => *code >= 500,
+        _ => false,
+    }
+}
@@ -0,0 +1,89 @@
+//! Access token shared by all clones of a client, optionally refreshed through a `GoogleAuthManager`.
+
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use crate::auth::{AuthSession, GoogleAuthManager};
+use crate::error::AdapterError;
+
+pub(crate) struct Credentials {
+    session: RwLock<AuthSession>,
+    manager: Option<Arc<GoogleAuthManager>>,
+    /// Serializes refreshes, so concurrent 401s trigger only one of them.
+    refresh_lock: tokio::sync::Mutex<()>,
+}
+
+impl Credentials {
+    pub(crate) fn fixed(access_token: String) -> Self {
+        Self::new(access_token, None)
+    }
+
+    /// Starts without a token, the first request fetches one from the manager.
+    pub(crate) fn managed(manager: Arc<GoogleAuthManager>) -> Self {
+        Self::new(String::new(), Some(manager))
+    }
+
+    fn new(access_token: String, manager: Option<Arc<GoogleAuthManager>>) -> Self {
+        Self {
+            session: RwLock::new(AuthSession {
+                access_token,
+                project_id: String::new(),
+                expires_at: None,
+            }),
+            manager,
+            refresh_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn token(&self) -> String {
+        self.session.read().unwrap().access_token.clone()
+    }
+
+    /// Copy of these credentials with a known expiry; the original stays untouched.
+    pub(crate) fn with_expiry(&self, expires_at: SystemTime) -> Self {
+        let mut session = self.session.read().unwrap().clone();
+        session.expires_at = Some(expires_at);
+        Self {
+            session: RwLock::new(session),
+            manager: self.manager.clone(),
+            refresh_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub(crate) fn can_refresh(&self) -> bool {
+        self.manager.is_some()
+    }
+
+    /// Makes sure a usable token is present: refreshes a missing or expired one when a manager
+    /// is available, otherwise fails on a token known to have expired.
+    pub(crate) async fn ensure_valid(&self) -> Result<(), AdapterError> {
+        let (token, expired) = {
+            let session = self.session.read().unwrap();
+            let expired = session.expires_at.is_some_and(|at| at <= SystemTime::now());
+            (session.access_token.clone(), expired || session.access_token.is_empty())
+        };
+
+        match (expired, self.can_refresh()) {
+            (false, _) => Ok(()),
+            (true, true) => self.refresh(&token).await,
+            (true, false) => Err(AdapterError::AuthError("token expired".to_string())),
+        }
+    }
+
+    /// Refreshes the token, unless another request already replaced `stale` in the meantime.
+    pub(crate) async fn refresh(&self, stale: &str) -> Result<(), AdapterError> {
+        let Some(manager) = &self.manager else {
+            return Err(AdapterError::AuthError("token expired and no auth manager to refresh it".to_string()));
+        };
+
+        let _guard = self.refresh_lock.lock().await;
+        let mut session = self.session.read().unwrap().clone();
+        if session.access_token != stale {
+            return Ok(());
+        }
+
+        log::debug!("Refreshing access token");
+        manager.refresh(&mut session).await?;
+        *self.session.write().unwrap() = session;
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+//! Capture of the last request/response pair, for attaching to bug reports.
+
+use std::time::{Duration, SystemTime};
+use serde::Serialize;
+
+/// The last exchange with the API, see [`crate::CodeAssistClient::with_debug_capture`].
+#[derive(Debug, Clone, Serialize)]
+pub struct DebugExchange {
+    /// API method, e.g. "generateContent".
+    pub method: String,
+    /// The envelope that was sent, with inline binary data redacted.
+    pub request: serde_json::Value,
+    /// Raw response body. `None` on error and for streams (chunks are not retained).
+    pub response: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub started_at: SystemTime,
+    pub duration: Duration,
+}
+
+/// Parses an encoded envelope and replaces inline binary data with a size note.
+pub(crate) fn redacted_request(body: &[u8]) -> serde_json::Value {
+    let mut value = serde_json::from_slice(body).unwrap_or(serde_json::Value::Null);
+    redact(&mut value);
+    value
+}
+
+fn redact(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                if key == "inlineData" || key == "inline_data" {
+                    if let Some(serde_json::Value::String(data)) = child.get_mut("data") {
+                        *data = format!("<{} bytes of base64 redacted>", data.len());
+                    }
+                } else {
+                    redact(child);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
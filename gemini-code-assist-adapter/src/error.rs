@@ -1,5 +1,8 @@
 use thiserror::Error;
 
+/// Boxed error used to keep the original cause of a failure.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
 #[derive(Debug, Error)]
 pub enum AdapterError {
     #[error("HTTP Request failed: {0}")]
@@ -9,8 +12,538 @@ pub enum AdapterError {
     SerdeError(#[from] serde_json::Error),
 
     #[error("API returned error: {code} - {message}")]
-    ApiError { code: u16, message: String },
+    ApiError {
+        code: u16,
+        /// Canonical status from the error body, if the server sent one.
+        status: Option<GoogleStatus>,
+        /// `error.message` of the body, or the raw body if it isn't a Google error.
+        message: String,
+        /// `error.details` of the body (`ErrorInfo`, `QuotaFailure`, ...).
+        details: Vec<serde_json::Value>,
+    },
 
     #[error("Stream error: {0}")]
-    StreamError(String),
+    StreamError(#[source] BoxError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Authentication failed: {0}")]
+    AuthError(String),
+
+    #[error("{part} was blocked: {reason}")]
+    ContentBlocked {
+        part: BlockedPart,
+        reason: String,
+        ratings: Vec<gemini_rust::SafetyRating>,
+    },
+
+    /// Failure of an identical in-flight request this call was deduplicated into.
+    #[error(transparent)]
+    Deduplicated(std::sync::Arc<AdapterError>),
+
+    /// Copy of an error that can't be cloned (HTTP client, I/O, ...), see [`Self::Deduplicated`].
+    /// Keeps the category, HTTP status code and retryability of the original.
+    #[error("{message}")]
+    Detached {
+        kind: &'static str,
+        code: Option<u16>,
+        retryable: bool,
+        message: String,
+    },
+
+    #[error("All {} requests failed", .0.len())]
+    AllFailed(Vec<AdapterError>),
+
+    #[error("Invalid client configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("No Google Cloud project available: select an existing project or create one in the Cloud console")]
+    NoProject,
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    #[error("Deadline exceeded")]
+    Timeout,
+
+    #[error("Service temporarily unavailable, retry in {retry_after:?}")]
+    CircuitOpen { retry_after: std::time::Duration },
+
+    #[error("OAuth flow failed: {0}")]
+    OAuth(#[from] yup_oauth2::Error),
+
+    #[error("Project {0} was not found or is not accessible with this account")]
+    ProjectNotFound(String),
+
+    #[error("Model {0} is not available on Code Assist (unknown or retired)")]
+    ModelNotFound(String),
+
+    #[error("Onboarding failed: {message} (code {code})")]
+    OnboardingFailed { code: i32, message: String },
+}
+
+/// Extracts the exhausted quota metric from the `details` of a Google error body.
+/// It is reported either in an `ErrorInfo` (`metadata.quota_metric`) or in a `QuotaFailure` (`violations[].subject`).
+fn quota_metric(details: &[serde_json::Value]) -> Option<String> {
+    details.iter().find_map(|detail| {
+        detail
+            .pointer("/metadata/quota_metric")
+            .or_else(|| detail.pointer("/violations/0/subject"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    })
+}
+
+/// Returns the API service (and consumer project, if reported) from a `SERVICE_DISABLED`
+/// `ErrorInfo` in the `details` of a Google error body.
+fn disabled_service(details: &[serde_json::Value]) -> Option<(String, Option<String>)> {
+    let info = details
+        .iter()
+        .find(|d| d.get("reason").and_then(|r| r.as_str()) == Some("SERVICE_DISABLED"))?;
+    let service = info
+        .pointer("/metadata/service")
+        .and_then(|v| v.as_str())
+        .unwrap_or("cloudaicompanion.googleapis.com");
+    let consumer = info
+        .pointer("/metadata/consumer")
+        .and_then(|v| v.as_str())
+        .map(|c| c.trim_start_matches("projects/").to_string());
+
+    Some((service.to_string(), consumer))
+}
+
+/// Which side of the exchange was blocked by the safety filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockedPart {
+    Prompt,
+    Response,
+}
+
+impl std::fmt::Display for BlockedPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Prompt => f.write_str("Prompt"),
+            Self::Response => f.write_str("Response"),
+        }
+    }
+}
+
+/// Google canonical error status (the `error.status` field of an API error body).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GoogleStatus {
+    InvalidArgument,
+    PermissionDenied,
+    ResourceExhausted,
+    Unauthenticated,
+    NotFound,
+    Internal,
+    Unavailable,
+    /// Any status not covered above, kept verbatim.
+    Unknown(String),
+}
+
+impl GoogleStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::InvalidArgument => "INVALID_ARGUMENT",
+            Self::PermissionDenied => "PERMISSION_DENIED",
+            Self::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            Self::Unauthenticated => "UNAUTHENTICATED",
+            Self::NotFound => "NOT_FOUND",
+            Self::Internal => "INTERNAL",
+            Self::Unavailable => "UNAVAILABLE",
+            Self::Unknown(s) => s,
+        }
+    }
+}
+
+impl From<&str> for GoogleStatus {
+    fn from(s: &str) -> Self {
+        match s {
+            "INVALID_ARGUMENT" => Self::InvalidArgument,
+            "PERMISSION_DENIED" => Self::PermissionDenied,
+            "RESOURCE_EXHAUSTED" => Self::ResourceExhausted,
+            "UNAUTHENTICATED" => Self::Unauthenticated,
+            "NOT_FOUND" => Self::NotFound,
+            "INTERNAL" => Self::Internal,
+            "UNAVAILABLE" => Self::Unavailable,
+            other => Self::Unknown(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for GoogleStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl AdapterError {
+    /// Canonical Google status of an `ApiError`, if known.
+    pub fn status(&self) -> Option<&GoogleStatus> {
+        match self {
+            Self::Deduplicated(inner) => inner.status(),
+            Self::ApiError { status, .. } => status.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Copy of the error for callers joined into a deduplicated request. Errors that can't be
+    /// cloned (HTTP client, I/O, ...) are passed on as [`Self::Detached`].
+    pub(crate) fn duplicate(&self) -> AdapterError {
+        match self {
+            Self::ApiError { code, status, message, details } => Self::ApiError {
+                code: *code,
+                status: status.clone(),
+                message: message.clone(),
+                details: details.clone(),
+            },
+            Self::AuthError(message) => Self::AuthError(message.clone()),
+            Self::ContentBlocked { part, reason, ratings } => Self::ContentBlocked {
+                part: *part,
+                reason: reason.clone(),
+                ratings: ratings.clone(),
+            },
+            Self::Deduplicated(inner) => Self::Deduplicated(inner.clone()),
+            Self::AllFailed(errors) => Self::AllFailed(errors.iter().map(Self::duplicate).collect()),
+            Self::InvalidConfig(message) => Self::InvalidConfig(message.clone()),
+            Self::NoProject => Self::NoProject,
+            Self::Cancelled => Self::Cancelled,
+            Self::Timeout => Self::Timeout,
+            Self::CircuitOpen { retry_after } => Self::CircuitOpen { retry_after: *retry_after },
+            Self::ProjectNotFound(project) => Self::ProjectNotFound(project.clone()),
+            Self::ModelNotFound(model) => Self::ModelNotFound(model.clone()),
+            Self::OnboardingFailed { code, message } => Self::OnboardingFailed { code: *code, message: message.clone() },
+            Self::Detached { kind, code, retryable, message } => Self::Detached {
+                kind,
+                code: *code,
+                retryable: *retryable,
+                message: message.clone(),
+            },
+            other => Self::Detached {
+                kind: other.kind(),
+                code: other.status_code(),
+                retryable: other.is_retryable(),
+                message: other.to_string(),
+            },
+        }
+    }
+
+    /// Whether a 404 is about the requested model rather than the project: Code Assist names
+    /// the model in the message (or in the details) when it doesn't serve it.
+    pub(crate) fn is_model_not_found(&self, model: &str) -> bool {
+        match self {
+            Self::Deduplicated(inner) => inner.is_model_not_found(model),
+            Self::ApiError { code: 404, message, details, .. } => {
+                let mentions_model = |text: &str| {
+                    let text = text.to_lowercase();
+                    text.contains("model") || text.contains(&model.to_lowercase())
+                };
+                mentions_model(message) || details.iter().any(|detail| mentions_model(&detail.to_string()))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the request was rejected because the Code Assist API (or another API it needs)
+    /// is not enabled for the project.
+    pub fn is_service_disabled(&self) -> bool {
+        match self {
+            Self::Deduplicated(inner) => inner.is_service_disabled(),
+            Self::ApiError { details, .. } => disabled_service(details).is_some(),
+            _ => false,
+        }
+    }
+
+    /// Request id from a `RequestInfo` in the error details, to quote when contacting support.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            Self::Deduplicated(inner) => inner.request_id(),
+            Self::ApiError { details, .. } => details
+                .iter()
+                .find_map(|d| d.get("requestId").and_then(|id| id.as_str())),
+            _ => None,
+        }
+    }
+
+    /// Builds an `ApiError` from a non-success HTTP response.
+    pub(crate) async fn from_response(response: reqwest::Response) -> Self {
+        let code = response.status().as_u16();
+        let body = response.text().await.unwrap_or_default();
+        let error = Self::from_body(code, body);
+        if let Some(request_id) = error.request_id() {
+            log::debug!("Request {} failed with {}", request_id, code);
+        }
+        error
+    }
+
+    /// Builds an `ApiError` from a status code and raw error body, e.g.
+    /// `{"error": {"code": 429, "message": "...", "status": "RESOURCE_EXHAUSTED", "details": [...]}}`.
+    pub(crate) fn from_body(code: u16, body: String) -> Self {
+        #[derive(serde::Deserialize)]
+        struct ErrorBody {
+            error: ErrorDetails,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct ErrorDetails {
+            status: Option<String>,
+            message: Option<String>,
+            #[serde(default)]
+            details: Vec<serde_json::Value>,
+        }
+
+        match serde_json::from_str::<ErrorBody>(&body) {
+            Ok(ErrorBody { error }) => Self::ApiError {
+                code,
+                status: error.status.map(|s| GoogleStatus::from(s.as_str())),
+                message: error.message.unwrap_or(body),
+                details: error.details,
+            },
+            Err(_) => Self::ApiError { code, status: None, message: body, details: Vec::new() },
+        }
+    }
+
+    /// Turns a `RESOURCE_EXHAUSTED` error into an actionable message mentioning the project
+    /// and, when the server reports it, the exhausted quota metric.
+    pub(crate) fn with_quota_hint(self, project_id: &str) -> Self {
+        match self {
+            Self::ApiError { code, status: Some(GoogleStatus::ResourceExhausted), message, details } => {
+                let metric = quota_metric(&details)
+                    .map(|m| format!(" (quota metric: {})", m))
+                    .unwrap_or_default();
+
+                Self::ApiError {
+                    code,
+                    status: Some(GoogleStatus::ResourceExhausted),
+                    message: format!(
+                        "Quota exhausted for project {}{}; upgrade the tier or try again tomorrow. Details: {}",
+                        project_id, metric, message
+                    ),
+                    details,
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Turns a `PERMISSION_DENIED` caused by a disabled API into a message with a direct
+    /// console link to enable it for the project.
+    pub(crate) fn with_service_disabled_hint(self, project_id: &str) -> Self {
+        match self {
+            Self::ApiError { code, status: Some(GoogleStatus::PermissionDenied), message, details } => {
+                let Some((service, consumer)) = disabled_service(&details) else {
+                    return Self::ApiError { code, status: Some(GoogleStatus::PermissionDenied), message, details };
+                };
+                let project = Some(project_id.to_string())
+                    .filter(|p| !p.is_empty())
+                    .or(consumer)
+                    .unwrap_or_default();
+
+                Self::ApiError {
+                    code,
+                    status: Some(GoogleStatus::PermissionDenied),
+                    message: format!(
+                        "The {} API is not enabled for project {}. Enable it at https://console.cloud.google.com/apis/library/{}?project={} and try again. Details: {}",
+                        service, project, service, project, message
+                    ),
+                    details,
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Prefixes the message of an `ApiError` with some context.
+    pub(crate) fn context(self, context: &str) -> Self {
+        match self {
+            Self::ApiError { code, status, message, details } => Self::ApiError {
+                code,
+                status,
+                message: format!("{}: {}", context, message),
+                details,
+            },
+            other => other,
+        }
+    }
+}
+
+/// Flattened, serializable form of an [`AdapterError`], for sending errors over
+/// channels/IPC or into structured logs.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SerializableAdapterError {
+    /// Stable error category, e.g. "api" or "stream".
+    pub kind: String,
+    /// HTTP status code, if the error has one.
+    pub code: Option<u16>,
+    pub message: String,
+}
+
+impl AdapterError {
+    /// Stable name of the error category.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::RequestFailed(_) => "request_failed",
+            Self::SerdeError(_) => "serde",
+            Self::ApiError { .. } => "api",
+            Self::StreamError(_) => "stream",
+            Self::Io(_) => "io",
+            Self::AuthError(_) => "auth",
+            Self::ContentBlocked { .. } => "content_blocked",
+            Self::Deduplicated(inner) => inner.kind(),
+            Self::Detached { kind, .. } => kind,
+            Self::AllFailed(_) => "all_failed",
+            Self::InvalidConfig(_) => "invalid_config",
+            Self::NoProject => "no_project",
+            Self::Cancelled => "cancelled",
+            Self::Timeout => "timeout",
+            Self::CircuitOpen { .. } => "circuit_open",
+            Self::OAuth(_) => "oauth",
+            Self::OnboardingFailed { .. } => "onboarding_failed",
+            Self::ProjectNotFound(_) => "project_not_found",
+            Self::ModelNotFound(_) => "model_not_found",
+        }
+    }
+
+    /// HTTP status code, if the error has one.
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            Self::Deduplicated(inner) => inner.status_code(),
+            Self::Detached { code, .. } => *code,
+            Self::ApiError { code, .. } => Some(*code),
+            Self::RequestFailed(e) => e.status().map(|s| s.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// Whether the failure is transient, so sending the same request again may succeed:
+    /// timeouts and connection failures, broken streams, 429 and 5xx responses.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Deduplicated(inner) => inner.is_retryable(),
+            Self::Detached { retryable, .. } => *retryable,
+            Self::RequestFailed(e) if e.is_timeout() || e.is_connect() => true,
+            Self::StreamError(_) => true,
+            Self::ApiError { code, .. } => crate::RetryPolicy::is_retryable(*code),
+            _ => false,
+        }
+    }
+
+    pub fn to_serializable(&self) -> SerializableAdapterError {
+        if let Self::Deduplicated(inner) = self {
+            return inner.to_serializable();
+        }
+
+        SerializableAdapterError {
+            kind: self.kind().to_string(),
+            code: self.status_code(),
+            message: self.to_string(),
+        }
+    }
+}
+
+impl serde::Serialize for AdapterError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_serializable().serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Body returned by cloudcode-pa when the Code Assist API is disabled for the project.
+    const SERVICE_DISABLED: &str = r#"{
+  "error": {
+    "code": 403,
+    "message": "Gemini for Google Cloud API has not been used in project my-project before or it is disabled. Enable it by visiting https://console.developers.google.com/apis/api/cloudaicompanion.googleapis.com/overview?project=my-project then retry.",
+    "status": "PERMISSION_DENIED",
+    "details": [
+      {
+        "@type": "type.googleapis.com/google.rpc.ErrorInfo",
+        "reason": "SERVICE_DISABLED",
+        "domain": "googleapis.com",
+        "metadata": {
+          "consumer": "projects/my-project",
+          "service": "cloudaicompanion.googleapis.com"
+        }
+      },
+      {
+        "@type": "type.googleapis.com/google.rpc.RequestInfo",
+        "requestId": "a1b2c3d4e5f6"
+      }
+    ]
+  }
+}"#;
+
+    #[test]
+    fn parses_google_error_body() {
+        let error = AdapterError::from_body(403, SERVICE_DISABLED.to_string());
+
+        assert_eq!(error.status_code(), Some(403));
+        assert_eq!(error.status(), Some(&GoogleStatus::PermissionDenied));
+        assert_eq!(error.request_id(), Some("a1b2c3d4e5f6"));
+        assert!(error.is_service_disabled());
+        assert!(!error.is_retryable());
+        match &error {
+            AdapterError::ApiError { message, details, .. } => {
+                assert!(message.starts_with("Gemini for Google Cloud API has not been used"));
+                assert_eq!(details.len(), 2);
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn service_disabled_hint_links_console() {
+        let error = AdapterError::from_body(403, SERVICE_DISABLED.to_string()).with_service_disabled_hint("");
+        let message = error.to_string();
+        assert!(message.contains("cloudaicompanion.googleapis.com API is not enabled for project my-project"), "{}", message);
+        assert!(message.contains("https://console.cloud.google.com/apis/library/cloudaicompanion.googleapis.com?project=my-project"));
+    }
+
+    #[test]
+    fn duplicate_keeps_kind_code_and_retryability() {
+        let io = AdapterError::Io(std::io::Error::other("disk full")).duplicate();
+        assert_eq!(io.kind(), "io");
+        assert!(!io.is_retryable());
+        assert_eq!(io.to_string(), "IO error: disk full");
+
+        let stream = AdapterError::StreamError("connection reset".into()).duplicate();
+        assert_eq!(stream.kind(), "stream");
+        assert!(stream.is_retryable());
+
+        let api = AdapterError::from_body(503, "overloaded".to_string()).duplicate();
+        assert_eq!(api.status_code(), Some(503));
+        assert_eq!(api.kind(), "api");
+    }
+
+    #[test]
+    fn quota_hint_names_metric() {
+        let body = r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED", "details": [
+            {"@type": "type.googleapis.com/google.rpc.QuotaFailure", "violations": [{"subject": "generate_requests_per_day"}]}
+        ]}}"#;
+        let error = AdapterError::from_body(429, body.to_string()).with_quota_hint("my-project");
+
+        assert!(error.is_retryable());
+        assert!(error.to_string().contains("quota metric: generate_requests_per_day"));
+    }
+
+    #[test]
+    fn falls_back_to_raw_body() {
+        let body = "<html><body>502 Bad Gateway</body></html>";
+        let error = AdapterError::from_body(502, body.to_string());
+
+        assert_eq!(error.status(), None);
+        assert_eq!(error.request_id(), None);
+        assert!(error.is_retryable());
+        match error {
+            AdapterError::ApiError { message, details, .. } => {
+                assert_eq!(message, body);
+                assert!(details.is_empty());
+            }
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
 }
@@ -1,31 +1,235 @@
 pub mod error;
 pub mod models;
 pub mod auth;
+pub mod builder;
+pub mod debug;
+pub mod metrics;
+pub mod request;
+pub mod response;
+pub mod retry;
+pub mod stream;
+mod circuit;
+mod credentials;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 
+pub use builder::CodeAssistClientBuilder;
+pub use request::RequestBuilder;
+pub use retry::RetryPolicy;
+
+// Re-exported so callers don't need their own (version-matched) gemini-rust dependency.
+pub use gemini_rust;
+pub use tokio_util::sync::CancellationToken;
+pub use gemini_rust::{
+    Blob, Candidate, Content, FileData, FinishReason, FunctionCall, FunctionResponse,
+    GenerateContentRequest, GenerationConfig, GenerationResponse, Part, PromptFeedback, Role,
+    SafetyRating, SafetySetting, ThinkingConfig, Tool, ToolConfig, UsageMetadata,
+};
+
+use std::collections::HashMap;
 use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::{Instant, SystemTime};
 use futures::{Stream, StreamExt};
+use futures::future::{BoxFuture, FutureExt, Shared};
+use futures::stream::FuturesUnordered;
+use bytes::Bytes;
 use reqwest::Client;
 use eventsource_stream::Eventsource;
-use gemini_rust::{GenerateContentRequest, GenerationResponse};
-use crate::error::AdapterError;
-use crate::models::{ClientMetadata, CodeAssistEnvelope, CodeAssistResponseEnvelope, LoadCodeAssistRequest, LoadCodeAssistResponse, LroResponse, OnboardUserRequest};
+use crate::auth::GoogleAuthManager;
+use crate::circuit::CircuitBreaker;
+use crate::credentials::Credentials;
+use crate::debug::DebugExchange;
+use crate::error::{AdapterError, GoogleStatus};
+use crate::metrics::{Metrics, NoopMetrics, RequestLatencies};
+use crate::stream::{GenerationEvent, JsonArraySplitter, StreamEvent, StreamTransport};
+use crate::models::{ClientMetadata, CodeAssistEnvelope, CodeAssistResponseEnvelope, CountTokensResponse, HandshakeResult, LoadCodeAssistRequest, LoadCodeAssistResponse, LroResponse, ModelInfo, OnboardUserRequest, Tier, TierKind};
+
+/// Inline request payload limit of the API.
+const DEFAULT_MAX_REQUEST_BYTES: usize = 20 * 1024 * 1024;
+
+/// Aliases every client starts with, see [`CodeAssistClient::with_model_alias`].
+const BUILTIN_MODEL_ALIASES: &[(&str, &str)] = &[
+    ("fast", "gemini-2.5-flash"),
+    ("smart", "gemini-2.5-pro"),
+    ("cheap", "gemini-2.5-flash-lite"),
+];
 
-// const BASE_URL: &str = "https://cloudaicompanion.googleapis.com/v1internal";
+// How often a dropped stream is resumed before giving up, see `with_stream_auto_resume`
+const STREAM_RESUME_MAX_ATTEMPTS: u32 = 3;
+const STREAM_RESUME_PROMPT: &str = "Your previous answer was cut off. Continue exactly where it stopped, without repeating anything.";
+
+const DEFAULT_DONE_SENTINEL: &str = "[DONE]";
+
+// Default endpoint, see `with_base_url` (e.g. "https://cloudaicompanion.googleapis.com/v1internal")
 const BASE_URL: &str = "https://cloudcode-pa.googleapis.com/v1internal";
 
 
+/// Upload progress callback: bytes sent so far and the total body size, if known.
+pub type ProgressCallback = Arc<dyn Fn(u64, Option<u64>) + Send + Sync>;
+
+/// Chunk size used when streaming a request body with progress reporting.
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+type InFlight = Shared<BoxFuture<'static, Result<GenerationResponse, Arc<AdapterError>>>>;
+
+/// Removes a deduplicated request from the in-flight map once it has finished (or its
+/// caller went away).
+struct InFlightGuard {
+    in_flight: Arc<std::sync::Mutex<HashMap<String, InFlight>>>,
+    key: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+/// Smallest possible request, for cheap probes of the API.
+fn probe_request() -> GenerateContentRequest {
+    GenerateContentRequest {
+        contents: vec![Content::text("ping").with_role(Role::User)],
+        generation_config: None,
+        safety_settings: None,
+        tools: None,
+        tool_config: None,
+        system_instruction: None,
+        cached_content: None,
+    }
+}
+
+/// Hash of the serialized request, so different requests sent with the same idempotency key
+/// are never merged.
+fn request_hash(request: &GenerateContentRequest) -> Result<u64, AdapterError> {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    serde_json::to_vec(request)?.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// Stream of response chunks returned by [`CodeAssistClient::generate_content_stream`].
+pub type ResponseStream = Pin<Box<dyn Stream<Item = Result<GenerationResponse, AdapterError>> + Send>>;
+
 /// Adapter client for working with Gemini Code Assist.
+///
+/// Clones are cheap and share the access token, the project and the model: updating them
+/// through one handle (e.g. [`Self::set_project_id`] on a client shared via `Arc`) affects
+/// every clone. The `with_*` builder methods give the returned client its own copy instead.
 #[derive(Clone)]
 pub struct CodeAssistClient {
     http_client: Client,
-    project_id: String,
-    auth_token: String,
-    model: String,
+    project_id: Arc<RwLock<String>>,
+    credentials: Arc<Credentials>,
+    model: Arc<RwLock<String>>,
+    metrics: Arc<dyn Metrics>,
+    auto_onboard: bool,
+    tier_id: Option<String>,
+    max_request_bytes: usize,
+    done_sentinel: String,
+    model_aliases: HashMap<String, String>,
+    thinking_budget: Option<i32>,
+    model_fallbacks: Vec<String>,
+    idempotency_key: Option<String>,
+    query_params: Vec<(String, String)>,
+    ide_type: String,
+    ide_version: String,
+    plugin_version: String,
+    model_channel: Option<String>,
+    upload_progress: Option<ProgressCallback>,
+    base_url: String,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    retry_policy: Option<RetryPolicy>,
+    onboarding_policy: RetryPolicy,
+    latency: Option<Arc<RequestLatencies>>,
+    stream_auto_resume: bool,
+    stream_transport: StreamTransport,
+    stream_idle_timeout: Option<std::time::Duration>,
+    debug_capture: Option<Arc<std::sync::Mutex<Option<DebugExchange>>>>,
+    in_flight: Arc<std::sync::Mutex<HashMap<String, InFlight>>>,
+    last_trace_id: Arc<std::sync::Mutex<Option<String>>>,
+}
+
+/// Checks an SSE `data` payload against the end-of-stream sentinel.
+/// Tolerates surrounding whitespace, a duplicated `data:` prefix (seen with some proxies) and case.
+fn is_done_sentinel(data: &str, sentinel: &str) -> bool {
+    let data = data.trim();
+    let data = data.strip_prefix("data:").map(str::trim).unwrap_or(data);
+    data.eq_ignore_ascii_case(sentinel.trim())
+}
+
+/// Whether an error means the model is unavailable (not found in the tier or out of quota),
+/// so the next model of the fallback chain should be tried.
+fn should_fall_back(error: &AdapterError) -> bool {
+    match error {
+        AdapterError::ApiError { code, status, .. } => {
+            matches!(code, 404 | 429)
+                || matches!(status, Some(GoogleStatus::NotFound | GoogleStatus::ResourceExhausted))
+        }
+        _ => false,
+    }
+}
+
+/// Picks the newest model of a channel's family, e.g. "flash-latest" -> "gemini-2.5-flash".
+/// Only plain and `-preview` models count (no "-lite", no dated snapshots); with equal
+/// versions the stable one wins.
+fn best_channel_match(channel: &str, models: &[&str]) -> Option<String> {
+    let family = channel.strip_suffix("-latest").unwrap_or(channel);
+
+    models
+        .iter()
+        .filter_map(|name| {
+            let rest = name.strip_prefix("gemini-")?;
+            let (version, rest) = rest.split_once('-')?;
+            let version: Vec<u32> = version.split('.').map(|v| v.parse().ok()).collect::<Option<_>>()?;
+            let stable = match rest.strip_prefix(family)? {
+                "" => true,
+                "-preview" => false,
+                _ => return None,
+            };
+            Some(((version, stable), name))
+        })
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, name)| name.to_string())
+}
+
+/// Short state of an onboarding operation, for progress reports.
+fn lro_state(lro: &LroResponse) -> &'static str {
+    match (lro.done, &lro.error) {
+        (_, Some(_)) => "FAILED",
+        (Some(true), None) => "DONE",
+        _ => "RUNNING",
+    }
+}
+
+/// Turns an operation that finished with an error into [`AdapterError::OnboardingFailed`].
+fn check_lro(lro: LroResponse) -> Result<LroResponse, AdapterError> {
+    match lro.error {
+        Some(status) => Err(AdapterError::OnboardingFailed { code: status.code, message: status.message }),
+        None => Ok(lro),
+    }
+}
+
+/// Runs `future` to completion, or fails with [`AdapterError::Cancelled`] (dropping it) as soon
+/// as `cancel` is triggered.
+async fn unless_cancelled<F: std::future::Future>(cancel: &CancellationToken, future: F) -> Result<F::Output, AdapterError> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(AdapterError::Cancelled),
+        output = future => Ok(output),
+    }
 }
 
+/// Bare model name as sent on the wire. Accepts `gemini-2.5-flash`, `models/gemini-2.5-flash`
+/// and fully qualified `projects/*/locations/*/models/gemini-2.5-flash` names; idempotent.
 fn sanitize_model_name(model: &str) -> String {
-    // Remove "models/" prefix if present
-    model.strip_prefix("models/").unwrap_or(model).to_string()
+    let model = model.trim();
+    let model = match model.rsplit_once("/models/") {
+        Some((_, name)) => name,
+        None => model.strip_prefix("models/").unwrap_or(model),
+    };
+    model.to_string()
 }
 
 
@@ -38,209 +242,1628 @@ impl CodeAssistClient {
     pub fn new(auth_token: String, project_id: String) -> Self {
         Self {
             http_client: Client::new(),
-            project_id,
-            auth_token,
-            model: "models/gemini-3-flash-preview".to_string(),
+            project_id: Arc::new(RwLock::new(project_id)),
+            credentials: Arc::new(Credentials::fixed(auth_token)),
+            model: Arc::new(RwLock::new("gemini-3-flash-preview".to_string())),
+            metrics: Arc::new(NoopMetrics),
+            auto_onboard: true,
+            tier_id: None,
+            max_request_bytes: DEFAULT_MAX_REQUEST_BYTES,
+            done_sentinel: DEFAULT_DONE_SENTINEL.to_string(),
+            model_aliases: BUILTIN_MODEL_ALIASES
+                .iter()
+                .map(|(alias, model)| (alias.to_string(), model.to_string()))
+                .collect(),
+            thinking_budget: None,
+            model_fallbacks: Vec::new(),
+            idempotency_key: None,
+            query_params: Vec::new(),
+            ide_type: models::DEFAULT_IDE_TYPE.to_string(),
+            ide_version: models::DEFAULT_CLI_VERSION.to_string(),
+            plugin_version: models::DEFAULT_CLI_VERSION.to_string(),
+            model_channel: None,
+            upload_progress: None,
+            base_url: BASE_URL.to_string(),
+            circuit_breaker: None,
+            retry_policy: None,
+            onboarding_policy: RetryPolicy::onboarding(),
+            latency: None,
+            stream_auto_resume: false,
+            stream_transport: StreamTransport::default(),
+            stream_idle_timeout: None,
+            debug_capture: None,
+            in_flight: Default::default(),
+            last_trace_id: Default::default(),
         }
     }
 
-    /// Activates the user/project in the Code Assist system.
-    pub async fn onboard_user(&mut self) -> Result<(), AdapterError> {
-        let url = format!("{}:onboardUser", BASE_URL);
+    /// Starts a [`CodeAssistClientBuilder`], which validates the configuration on `build`.
+    pub fn builder(auth_token: impl Into<String>) -> CodeAssistClientBuilder {
+        CodeAssistClientBuilder::new(auth_token)
+    }
 
-        let request = OnboardUserRequest {
-            tier_id: "free-tier".to_string(), // todo!
-            cloudaicompanion_project: Some(self.project_id.clone()),
-            metadata: ClientMetadata::default(),
-        };
+    /// Creates a client from a session, e.g. from [`GoogleAuthManager::authenticate`].
+    /// The token expiry is taken over when known, see [`Self::with_token_expiry`].
+    pub fn from_session(session: auth::AuthSession) -> Self {
+        let client = Self::new(session.access_token, session.project_id);
+        match session.expires_at {
+            Some(expires_at) => client.with_token_expiry(expires_at),
+            None => client,
+        }
+    }
 
-        log::debug!("Onboarding user for project: {}", self.project_id);
+    /// Creates a client that gets its access token from `manager` and refreshes it
+    /// transparently: ahead of time when the expiry is known, and once after a 401.
+    /// Clones of the client share the token, concurrent requests trigger a single refresh.
+    pub fn with_auth_manager(manager: Arc<GoogleAuthManager>, project_id: String) -> Self {
+        let mut client = Self::new(String::new(), project_id);
+        client.credentials = Arc::new(Credentials::managed(manager));
+        client
+    }
 
-        let mut lro: LroResponse = self.http_client
-            .post(&url)
-            .bearer_auth(&self.auth_token)
-            .json(&request)
+    /// Checks the configuration without any network call, so mistakes surface before
+    /// the first (often user-triggered) request.
+    pub fn validate(&self) -> Result<(), AdapterError> {
+        if self.credentials.token().trim().is_empty() && !self.credentials.can_refresh() {
+            return Err(AdapterError::InvalidConfig("the auth token is empty".into()));
+        }
+        if self.model().trim().is_empty() {
+            return Err(AdapterError::InvalidConfig("the model name is empty".into()));
+        }
+        if !self.base_url.starts_with("https://") && !self.base_url.starts_with("http://") {
+            return Err(AdapterError::InvalidConfig(format!("the base URL {} is not an http(s) URL", self.base_url)));
+        }
+        if self.base_url.ends_with('/') {
+            return Err(AdapterError::InvalidConfig(format!("the base URL {} must not end with a slash", self.base_url)));
+        }
+        Ok(())
+    }
+
+    /// Starts onboarding the user/project in the Code Assist system and returns the
+    /// long-running operation as reported by the server, without waiting for it.
+    /// If the operation is already done, the confirmed project is applied right away;
+    /// otherwise poll its `name` with [`Self::poll_onboarding`], or use
+    /// [`Self::onboard_user_blocking`] to wait with the default schedule.
+    pub async fn onboard_user(&mut self) -> Result<LroResponse, AdapterError> {
+        log::debug!("Onboarding user for project: {}", self.project_id());
+        let lro = check_lro(self.send_onboard_request().await?)?;
+        if let Some(project) = lro.project_id() {
+            log::debug!("Onboarding complete. Project confirmed: {}", project);
+            self.set_project_id(project.to_string());
+        }
+        Ok(lro)
+    }
+
+    /// Polls the onboarding operation `operation_name` once. Returns the confirmed project id
+    /// if the operation has finished with one, `None` while it is still running, and
+    /// [`AdapterError::OnboardingFailed`] if it failed.
+    pub async fn poll_onboarding(&self, operation_name: &str) -> Result<Option<String>, AdapterError> {
+        self.credentials.ensure_valid().await?;
+        let url = format!("{}/{}", self.base_url, operation_name);
+
+        let response = self.http_client
+            .get(&url)
+            .bearer_auth(self.credentials.token())
             .send()
-            .await?
-            .json()
             .await?;
 
+        if !response.status().is_success() {
+            return Err(AdapterError::from_response(response).await.context("Onboarding poll failed"));
+        }
+
+        let lro: LroResponse = response.json().await?;
+        log::debug!("Onboarding operation {} is {}", operation_name, lro_state(&lro));
+        Ok(check_lro(lro)?.project_id().map(str::to_string))
+    }
+
+    /// Onboards the user and waits for the operation to finish, polling it according to the
+    /// onboarding policy (see [`Self::with_onboarding_policy`]). Fails with
+    /// [`AdapterError::OnboardingFailed`] if the operation reports an error.
+    pub async fn onboard_user_blocking(&mut self) -> Result<(), AdapterError> {
+        self.onboard_user_with_progress(|_, _| {}, CancellationToken::new()).await
+    }
+
+    /// Same as [`Self::onboard_user_blocking`], but reports every poll of the onboarding operation to
+    /// `on_progress` (attempt starting at 1, and the operation state) and stops with
+    /// [`AdapterError::Cancelled`] as soon as `cancel` is triggered, aborting a request in flight.
+    pub async fn onboard_user_with_progress(
+        &mut self,
+        on_progress: impl Fn(u32, &str),
+        cancel: CancellationToken,
+    ) -> Result<(), AdapterError> {
+        log::debug!("Onboarding user for project: {}", self.project_id());
+
+        let mut lro = unless_cancelled(&cancel, self.send_onboard_request()).await??;
+
+        let policy = self.onboarding_policy;
         let mut attempts = 0;
-        while lro.done != Some(true) && attempts < 5 {
-            log::debug!("Onboarding in progress... waiting");
-            tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        on_progress(attempts + 1, lro_state(&lro));
+        lro = check_lro(lro)?;
+        while lro.done != Some(true) && attempts < policy.max_retries {
+            let delay = policy.delay(attempts + 1, None);
+            log::debug!("Onboarding in progress... waiting {:?}", delay);
+            unless_cancelled(&cancel, tokio::time::sleep(delay)).await?;
 
             // Repeat request (it is idempotent or returns status)
-            lro = self.http_client
-                .post(&url)
-                .bearer_auth(&self.auth_token)
-                .json(&request)
-                .send()
-                .await?
-                .json()
-                .await?;
+            lro = unless_cancelled(&cancel, self.send_onboard_request()).await??;
 
             attempts += 1;
+            on_progress(attempts + 1, lro_state(&lro));
+            lro = check_lro(lro)?;
         }
 
-        if let Some(resp) = lro.response {
-            if let Some(proj) = resp.cloudaicompanion_project {
-                log::debug!("Onboarding complete. Project confirmed: {}", proj.id);
-                self.project_id = proj.id; // Update ID if server issued a different one
-                return Ok(());
-            }
+        if let Some(proj) = lro.project_id() {
+            log::debug!("Onboarding complete. Project confirmed: {}", proj);
+            self.set_project_id(proj.to_string()); // Update ID if server issued a different one
+            return Ok(());
         }
 
         // If we are here, it means either done=true but no response, or timeout
-        log::debug!("Onboarding finished (assumed success or already done).");
+        if lro.done == Some(true) {
+            log::debug!("Onboarding finished (assumed success or already done).");
+        } else {
+            log::warn!("Onboarding did not complete after {} attempts, continuing with project {}", attempts + 1, self.project_id());
+        }
         Ok(())
     }
 
+    async fn send_onboard_request(&self) -> Result<LroResponse, AdapterError> {
+        let url = format!("{}:onboardUser", self.base_url);
+
+        let request = OnboardUserRequest {
+            tier_id: self.tier_id.clone().unwrap_or_else(|| TierKind::default().id().to_string()),
+            cloudaicompanion_project: Some(self.project_id()).filter(|p| !p.is_empty()),
+            metadata: self.client_metadata(),
+        };
+
+        self.credentials.ensure_valid().await?;
+        self.with_auth_retry(|| async {
+            let response = self.http_client
+                .post(&url)
+                .bearer_auth(self.credentials.token())
+                .json(&request)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(AdapterError::from_response(response).await.context("Onboarding failed"));
+            }
+
+            Ok(response.json().await?)
+        }).await
+    }
+
     pub async fn load_code_assist(&self) -> Result<String, AdapterError> {
-        let url = format!("{}:loadCodeAssist", BASE_URL);
+        Ok(self.load_code_assist_full().await?.project_id)
+    }
+
+    /// Performs the handshake and returns the effective project together with the user's tiers.
+    pub async fn load_code_assist_full(&self) -> Result<HandshakeResult, AdapterError> {
+        self.credentials.ensure_valid().await?;
+        let url = format!("{}:loadCodeAssist", self.base_url);
 
         // We try to send the project we found during login
         let request = LoadCodeAssistRequest {
-            cloudaicompanion_project: Some(self.project_id.clone()),
-            metadata: ClientMetadata::default(),
+            cloudaicompanion_project: Some(self.project_id()).filter(|p| !p.is_empty()),
+            metadata: self.client_metadata(),
         };
 
         let response = self.http_client
             .post(&url)
-            .bearer_auth(&self.auth_token)
+            .bearer_auth(self.credentials.token())
             .json(&request)
             .send()
             .await?;
 
         if !response.status().is_success() {
-            let code = response.status().as_u16();
-             let text = response.text().await.unwrap_or_default();
-             return Err(AdapterError::ApiError {
-                code,
-                message: format!("Handshake failed: {}", text),
-            });
+            return Err(AdapterError::from_response(response).await
+                .with_service_disabled_hint(&self.project_id())
+                .context("Handshake failed"));
         }
 
         let data: LoadCodeAssistResponse = response.json().await?;
 
         // Logic from setup.ts gemini-cli:
-        let effective_project = data.cloudaicompanion_project.unwrap_or(self.project_id.clone());
+        let effective_project = data.cloudaicompanion_project.unwrap_or_else(|| self.project_id());
+
+        // Without a project we can only continue if onboarding will provision a managed one
+        let tier_provides_project = data.current_tier.as_ref()
+            .or_else(|| data.allowed_tiers.as_deref().and_then(|tiers| tiers.iter().find(|t| t.is_default)))
+            .is_some_and(|t| t.user_defined_cloudaicompanion_project != Some(true));
+        if effective_project.is_empty() && !tier_provides_project {
+            return Err(AdapterError::NoProject);
+        }
 
         log::debug!("Handshake success. Tier: {:?}. Using Project: {}",
-            data.current_tier.map(|t| t.id),
+            data.current_tier.as_ref().map(|t| &t.id),
             effective_project
         );
 
-        Ok(effective_project)
+        Ok(HandshakeResult {
+            project_id: effective_project,
+            current_tier: data.current_tier,
+            allowed_tiers: data.allowed_tiers.unwrap_or_default(),
+        })
+    }
+
+    /// Creates a ready-to-use client: runs the handshake, applies the returned project id
+    /// and onboards the user (see [`Self::ensure_ready`]).
+    ///
+    /// When `project_id` is `None` the project is taken from the handshake.
+    pub async fn connect(
+        auth_token: String,
+        project_id: Option<String>,
+        model: impl Into<String>,
+    ) -> Result<Self, AdapterError> {
+        let mut client = Self::new(auth_token, project_id.unwrap_or_default()).with_model(model);
+        client.validate()?;
+        client.ensure_ready().await?;
+        Ok(client)
+    }
+
+    /// Runs the handshake and, unless disabled via [`Self::with_auto_onboard`], onboarding.
+    /// Handshake and onboarding failures are not fatal: the client keeps the provided project id.
+    /// The only exception is a handshake failure when no project id was provided at all.
+    pub async fn ensure_ready(&mut self) -> Result<(), AdapterError> {
+        match self.load_code_assist_full().await {
+            Ok(handshake) => {
+                // An explicitly chosen tier wins over the one suggested by the server
+                match handshake.onboarding_tier() {
+                    Some(tier) if self.tier_id.is_none() => self.tier_id = Some(tier.id.clone()),
+                    _ => {}
+                }
+                self.set_project_id(handshake.project_id);
+            }
+            Err(e) if self.project_id().is_empty() => return Err(e),
+            Err(e) => log::warn!("Handshake failed, falling back to project {}: {}", self.project_id(), e),
+        }
+
+        self.resolve_model_channel().await;
+
+        if !self.auto_onboard {
+            log::debug!("Auto-onboarding disabled, relying on the handshake only.");
+            return Ok(());
+        }
+
+        if let Err(e) = self.onboard_user_blocking().await {
+            log::warn!("Onboarding failed: {}", e);
+        }
+
+        Ok(())
+    }
+
+    /// Lists the tiers the user can onboard into, with their descriptions and privacy notices.
+    pub async fn list_tiers(&self) -> Result<Vec<Tier>, AdapterError> {
+        Ok(self.load_code_assist_full().await?.allowed_tiers)
+    }
+
+    /// Switches the client to another project: re-runs the handshake for it and onboards the
+    /// user if they are not onboarded there yet (unless auto-onboarding is disabled).
+    /// Unlike [`Self::ensure_ready`], failures are returned.
+    pub async fn switch_project(&mut self, project_id: String) -> Result<(), AdapterError> {
+        self.set_project_id(project_id);
+        self.tier_id = None;
+
+        let handshake = self.load_code_assist_full().await?;
+        if let Some(tier) = handshake.onboarding_tier() {
+            self.tier_id = Some(tier.id.clone());
+        }
+        let needs_onboarding = handshake.current_tier.is_none();
+        self.set_project_id(handshake.project_id);
+
+        if needs_onboarding && self.auto_onboard {
+            self.onboard_user_blocking().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Onboards into `tier` instead of the free tier, e.g. for users with a paid subscription.
+    /// Takes precedence over the tier suggested by the handshake in [`Self::ensure_ready`].
+    pub fn with_tier(mut self, tier: TierKind) -> Self {
+        self.tier_id = Some(tier.id().to_string());
+        self
+    }
+
+    /// Sets the tier used by [`Self::onboard_user`]. Defaults to "free-tier",
+    /// or to the tier picked from the handshake in [`Self::ensure_ready`].
+    /// See [`Self::with_tier`] for the well-known ones.
+    pub fn set_tier_id(&mut self, tier_id: impl Into<String>) {
+        self.tier_id = Some(tier_id.into());
     }
 
     // Helper method to update project_id inside client after handshake
-    pub fn set_project_id(&mut self, project_id: String) {
-        self.project_id = project_id;
+    /// Affects all clones of this client.
+    pub fn set_project_id(&self, project_id: String) {
+        *self.project_id.write().unwrap() = project_id;
+    }
+
+    /// Project requests are currently sent for.
+    pub fn project_id(&self) -> String {
+        self.project_id.read().unwrap().clone()
+    }
+
+    /// Model currently configured (before alias resolution).
+    pub fn model(&self) -> String {
+        self.model.read().unwrap().clone()
     }
 
+    /// Sets the model. Accepts either a model name or an alias registered
+    /// via [`Self::with_model_alias`] (aliases are resolved at request time).
+    /// A `models/` or `projects/*/locations/*/models/` prefix is dropped, so [`Self::model`]
+    /// always returns the bare name.
     pub fn with_model(mut self, model: impl Into<String>) -> Self {
-        self.model = model.into();
+        self.model = Arc::new(RwLock::new(sanitize_model_name(&model.into())));
+        self.model_channel = None;
         self
     }
 
-    /// Performs a standard (non-streaming) request.
-    /// Accepts standard `GenerateContentRequest` from gemini-rust.
-    pub async fn generate_content(
-        &self,
-        request: &GenerateContentRequest,
-    ) -> Result<GenerationResponse, AdapterError> {
-        let url = format!("{}:generateContent", BASE_URL);
-
-        let mut request_json = serde_json::to_value(request)?;
-        let session_id = uuid::Uuid::new_v4().to_string();
-        if let Some(obj) = request_json.as_object_mut() {
-            obj.insert("session_id".to_string(), serde_json::json!(session_id));
-        }
+    /// Follows a model channel such as "flash-latest" or "pro-latest" instead of a fixed name.
+    ///
+    /// The channel is resolved to the newest matching model of [`models::KNOWN_MODELS`] that
+    /// Code Assist accepts (see [`Self::verify_model`]) in [`Self::ensure_ready`] (or
+    /// [`Self::resolve_model_channel`]). Until then, or if none is accepted, the literal
+    /// `gemini-<channel>` name is used.
+    pub fn with_model_channel(mut self, channel: impl Into<String>) -> Self {
+        let channel = channel.into();
+        self.model = Arc::new(RwLock::new(format!("gemini-{}", channel)));
+        self.model_channel = Some(channel);
+        self
+    }
 
-        let envelope = CodeAssistEnvelope {
-            model: sanitize_model_name(&self.model),
-            project: self.project_id.clone(),
-            user_prompt_id: Some(uuid::Uuid::new_v4().to_string()),
-            request: request_json,
+    /// Resolves the channel set via [`Self::with_model_channel`] to a concrete model, once.
+    /// Returns the model in use afterwards.
+    pub async fn resolve_model_channel(&mut self) -> String {
+        let Some(channel) = self.model_channel.take() else {
+            return self.model();
         };
 
-        log::debug!("Sending Envelope: {}", serde_json::to_string_pretty(&envelope).unwrap());
-        let response = self.http_client
-            .post(&url)
-            .bearer_auth(&self.auth_token)
-            .json(&envelope)
-            .send()
-            .await?;
-
-        if !response.status().is_success() {
-            return Err(AdapterError::ApiError {
-                code: response.status().as_u16(),
-                message: response.text().await.unwrap_or_default(),
-            });
+        // Newest first, skipping candidates the backend rejects (e.g. a retired preview)
+        let mut candidates: Vec<&str> = models::KNOWN_MODELS.iter().map(|m| m.name).collect();
+        while let Some(model) = best_channel_match(&channel, &candidates) {
+            match self.verify_model(&model).await {
+                Ok(true) => {
+                    log::debug!("Model channel {} resolved to {}", channel, model);
+                    *self.model.write().unwrap() = model;
+                    return self.model();
+                }
+                Ok(false) => candidates.retain(|name| *name != model),
+                Err(e) => {
+                    log::warn!("Failed to resolve model channel {}, using {}: {}", channel, self.model(), e);
+                    return self.model();
+                }
+            }
         }
+        log::warn!("No available model matches channel {}, using {}", channel, self.model());
+        self.model()
+    }
 
-        let envelope_resp: CodeAssistResponseEnvelope = response.json().await?;
-        Ok(envelope_resp.response)
+    /// Lists the names of the models this account can use on Code Assist.
+    /// The backend has no listing of its own: these are the models of [`models::KNOWN_MODELS`]
+    /// that pass [`Self::verify_model`], so models newer than this crate are missing.
+    pub async fn list_models(&self) -> Result<Vec<String>, AdapterError> {
+        Ok(self.list_models_full().await?.into_iter().map(|m| m.name.to_string()).collect())
     }
 
-    /// Performs a streaming request.
-    /// Returns a stream of `GenerationResponse` (chunks).
-    pub async fn generate_content_stream(
-        &self,
-        request: &GenerateContentRequest,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<GenerationResponse, AdapterError>> + Send>>, AdapterError> {
-        let url = format!("{}:streamGenerateContent?alt=sse", BASE_URL);
-        let mut request_json = serde_json::to_value(request)?;
+    /// Same as [`Self::list_models`], but with the supported methods and token limits of
+    /// every model. Costs one `countTokens` request per known model, sent concurrently.
+    pub async fn list_models_full(&self) -> Result<Vec<ModelInfo>, AdapterError> {
+        let checks = models::KNOWN_MODELS.iter().map(|model| async move {
+            self.verify_model(model.name).await.map(|available| available.then_some(*model))
+        });
 
-        let session_id = uuid::Uuid::new_v4().to_string();
+        let mut available = Vec::new();
+        for result in futures::future::join_all(checks).await {
+            available.extend(result?);
+        }
+        Ok(available)
+    }
 
-        if let Some(obj) = request_json.as_object_mut() {
-            obj.insert("session_id".to_string(), serde_json::json!(session_id));
+    /// Checks that Code Assist serves `model` (an alias or a name, with or without `models/`)
+    /// to this account, to catch a typo or a retired preview model up front. Probes the model
+    /// with a token count of a one-word prompt; `Ok(false)` if the backend rejects the model.
+    pub async fn verify_model(&self, model: &str) -> Result<bool, AdapterError> {
+        match self.count_tokens_with_model(&probe_request(), &self.resolve_model(model)).await {
+            Ok(_) => Ok(true),
+            Err(e) if matches!(e.status(), Some(GoogleStatus::NotFound | GoogleStatus::InvalidArgument)) => {
+                log::debug!("Model {} is not available: {}", model, e);
+                Ok(false)
+            }
+            Err(e) => Err(e),
         }
+    }
 
-        let envelope = CodeAssistEnvelope {
-            model: sanitize_model_name(&self.model),
-            project: self.project_id.clone(),
-            user_prompt_id: Some(uuid::Uuid::new_v4().to_string()),
-            request: request_json,
+    /// Registers (or re-points) a friendly model name, e.g. `("fast", "models/gemini-2.0-flash")`.
+    /// Built-in aliases: "fast", "smart" and "cheap".
+    pub fn with_model_alias(mut self, alias: impl Into<String>, model: impl Into<String>) -> Self {
+        self.model_aliases.insert(alias.into(), model.into());
+        self
+    }
+
+    /// Attaches an encoded request, as a body that reports upload progress if requested.
+    /// The chunks are slices of `body`, not copies, and the length is still sent up front.
+    fn with_body(&self, request: reqwest::RequestBuilder, body: Bytes) -> reqwest::RequestBuilder {
+        let Some(progress) = self.upload_progress.clone() else {
+            return request.body(body);
         };
-        log::debug!("Sending Envelope: {}", serde_json::to_string_pretty(&envelope).unwrap());
 
+        let total = body.len();
+        let chunks = (0..total).step_by(UPLOAD_CHUNK_SIZE).map(move |start| {
+            let chunk = body.slice(start..total.min(start + UPLOAD_CHUNK_SIZE));
+            progress((start + chunk.len()) as u64, Some(total as u64));
+            Ok::<_, std::io::Error>(chunk)
+        });
 
-        let response = self.http_client
-            .post(&url)
-            .bearer_auth(&self.auth_token)
-            .json(&envelope)
-            .send()
-            .await?;
+        request
+            .header(reqwest::header::CONTENT_LENGTH, total)
+            .body(reqwest::Body::wrap_stream(futures::stream::iter(chunks)))
+    }
 
-        if !response.status().is_success() {
-            return Err(AdapterError::ApiError {
-                code: response.status().as_u16(),
-                message: response.text().await.unwrap_or_default(),
-            });
+    /// Runs a request and, if it fails with 401 and the client has an auth manager,
+    /// refreshes the token and runs it once more.
+    async fn with_auth_retry<T, F, Fut>(&self, send: F) -> Result<T, AdapterError>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<T, AdapterError>>,
+    {
+        let token = self.credentials.token();
+        match send().await {
+            Err(AdapterError::ApiError { code: 401, .. }) if self.credentials.can_refresh() => {
+                log::debug!("Got 401, refreshing the access token and retrying");
+                self.credentials.refresh(&token).await?;
+                send().await
+            }
+            result => result,
         }
+    }
 
-        let stream = response.bytes_stream().eventsource();
+    fn client_metadata(&self) -> ClientMetadata {
+        ClientMetadata {
+            ide_type: self.ide_type.clone(),
+            ide_version: self.ide_version.clone(),
+            plugin_version: self.plugin_version.clone(),
+            ..Default::default()
+        }
+    }
 
-        let mapped_stream = stream.map(|event_result| {
-            match event_result {
-                Ok(event) => {
-                    if event.data == "[DONE]" {
-                        return None;
-                    }
+    /// Model name sent to the API, with aliases resolved. Unknown names are used as-is.
+    fn resolve_model(&self, model: &str) -> String {
+        let model = self.model_aliases.get(model).map(String::as_str).unwrap_or(model);
+        sanitize_model_name(model)
+    }
 
-                    match serde_json::from_str::<CodeAssistResponseEnvelope>(&event.data) {
-                        Ok(envelope) => Some(Ok(envelope.response)),
-                        Err(e) => Some(Err(AdapterError::SerdeError(e))),
-                    }
-                }
-                Err(e) => Some(Err(AdapterError::StreamError(e.to_string()))),
-            }
-        })
-        .filter_map(|x| async { x }); // Remove None
+    /// Models to try in order: the configured one, then the fallbacks.
+    fn model_chain(&self) -> Vec<String> {
+        std::iter::once(&self.model())
+            .chain(&self.model_fallbacks)
+            .map(|m| self.resolve_model(m))
+            .collect()
+    }
+
+    /// Sets models to fall back to, in order, when the current one is unavailable
+    /// (`NOT_FOUND`, e.g. not available in the tier) or out of quota (`RESOURCE_EXHAUSTED`).
+    /// For streams the fallback only happens before the first byte is received.
+    pub fn with_model_fallbacks(mut self, models: Vec<impl Into<String>>) -> Self {
+        self.model_fallbacks = models.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sends `key` as the `user_prompt_id` of every request, so the server can deduplicate
+    /// retries of the same generation. Meant for one logical request, e.g. set on a clone
+    /// (or use [`Self::generate_content_idempotent`]).
+    ///
+    /// [`Self::generate_content`] on any clone of this client also deduplicates locally: while
+    /// the same request with the same key is running, further calls await its result instead
+    /// of sending a second one. The joined calls get its errors wrapped in
+    /// [`AdapterError::Deduplicated`]; the call that sent the request gets them as they are.
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Caps the model's internal reasoning to `tokens` on every request
+    /// (overrides `thinking_budget` in the request's generation config).
+    pub fn with_thinking_budget(mut self, tokens: i32) -> Self {
+        self.thinking_budget = Some(tokens);
+        self
+    }
+
+    /// Disables thinking for lower latency (a thinking budget of 0).
+    pub fn with_thinking_disabled(self) -> Self {
+        self.with_thinking_budget(0)
+    }
+
+    /// Enables or disables onboarding in [`Self::ensure_ready`] (enabled by default).
+    ///
+    /// Useful when users are provisioned out-of-band. Note that if the user is not actually
+    /// onboarded, the first generate request will fail.
+    pub fn with_auto_onboard(mut self, enabled: bool) -> Self {
+        self.auto_onboard = enabled;
+        self
+    }
+
+    /// Overrides the maximum serialized request size (20 MB by default).
+    /// Larger requests fail locally with a 413 `ApiError` instead of being sent.
+    pub fn with_max_request_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_request_bytes = max_bytes;
+        self
+    }
+
+    /// Overrides the SSE payload that marks the end of a stream (`[DONE]` by default).
+    pub fn with_done_sentinel(mut self, sentinel: impl Into<String>) -> Self {
+        self.done_sentinel = sentinel.into();
+        self
+    }
+
+    /// Appends extra query parameters to the `generateContent` / `streamGenerateContent` URLs,
+    /// e.g. to try out experimental backend flags. Values are URL-encoded.
+    pub fn with_query_params(mut self, params: Vec<(String, String)>) -> Self {
+        self.query_params = params;
+        self
+    }
+
+    /// Overrides the IDE type sent in the handshake metadata
+    /// (defaults to [`models::DEFAULT_IDE_TYPE`]).
+    pub fn with_ide_type(mut self, ide_type: impl Into<String>) -> Self {
+        self.ide_type = ide_type.into();
+        self
+    }
+
+    /// Overrides the IDE version sent in the handshake metadata
+    /// (defaults to [`models::DEFAULT_CLI_VERSION`]).
+    pub fn with_ide_version(mut self, version: impl Into<String>) -> Self {
+        self.ide_version = version.into();
+        self
+    }
+
+    /// Overrides the plugin version sent in the handshake metadata
+    /// (defaults to [`models::DEFAULT_CLI_VERSION`]).
+    pub fn with_plugin_version(mut self, version: impl Into<String>) -> Self {
+        self.plugin_version = version.into();
+        self
+    }
+
+    /// Tells the client when the access token expires (e.g. [`auth::AuthSession::expires_at`]).
+    /// Requests made after that fail right away with [`AdapterError::AuthError`] instead of
+    /// a round trip ending in a 401 (or refresh the token first, see [`Self::with_auth_manager`]).
+    /// Like the other builders this only affects the returned client: it stops sharing the
+    /// token with earlier clones.
+    pub fn with_token_expiry(mut self, expires_at: SystemTime) -> Self {
+        self.credentials = Arc::new(self.credentials.with_expiry(expires_at));
+        self
+    }
+
+    /// Reports the progress of request uploads, e.g. for large inline attachments.
+    /// The callback is invoked with the bytes sent so far and the total size. Progress is
+    /// approximate: it counts the chunks handed to the HTTP client, which runs a little ahead
+    /// of the network.
+    pub fn with_upload_progress(mut self, progress: ProgressCallback) -> Self {
+        self.upload_progress = Some(progress);
+        self
+    }
+
+    /// After `threshold` consecutive backend failures (network errors, 5xx), requests fail
+    /// immediately with [`AdapterError::CircuitOpen`] for `cooldown`. Afterwards a single trial
+    /// request is let through: success closes the circuit again, failure reopens it.
+    /// The state is shared between clones of this client.
+    pub fn with_circuit_breaker(mut self, threshold: u32, cooldown: std::time::Duration) -> Self {
+        self.circuit_breaker = Some(Arc::new(CircuitBreaker::new(threshold, cooldown)));
+        self
+    }
+
+    /// Uses a preconfigured HTTP client, e.g. with a proxy or custom timeouts.
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.http_client = client;
+        self
+    }
+
+    /// Overrides the internal API endpoint, e.g. for a regional endpoint or an auditing proxy.
+    /// Method names are appended as `{url}:generateContent`, so the URL must not end with a
+    /// slash (checked by [`Self::validate`]). Defaults to `https://cloudcode-pa.googleapis.com/v1internal`.
+    pub fn with_base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = url.into();
+        self
+    }
+
+    /// Retries `generateContent` requests failing with 429 or a transient 5xx according to
+    /// `policy`, honoring the server's `Retry-After`. Disabled by default.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// How [`Self::onboard_user_blocking`] polls the onboarding operation: `max_retries` polls
+    /// after the first request, with growing delays. Defaults to [`RetryPolicy::onboarding`].
+    pub fn with_onboarding_policy(mut self, policy: RetryPolicy) -> Self {
+        self.onboarding_policy = policy;
+        self
+    }
+
+    /// Records request latencies into local histograms, see [`Self::latency_stats`].
+    /// `generateContent` and streams are kept apart, for both the time to first byte and the
+    /// total time are recorded (see [`metrics::RequestLatencies`] for what each one covers).
+    pub fn with_latency_tracking(mut self, enabled: bool) -> Self {
+        self.latency = enabled.then(Default::default);
+        self
+    }
+
+    /// Latencies recorded so far, if tracking is enabled. Shared between clones of this client.
+    pub fn latency_stats(&self) -> Option<&RequestLatencies> {
+        self.latency.as_deref()
+    }
+
+    /// Resumes streams of [`Self::generate_content_stream_resumable`] that drop mid-generation,
+    /// by asking the model to continue from the text received so far. Costs extra tokens,
+    /// since the whole conversation is sent again.
+    pub fn with_stream_auto_resume(mut self, enabled: bool) -> Self {
+        self.stream_auto_resume = enabled;
+        self
+    }
+
+    /// Selects the wire format of streaming requests (SSE by default).
+    pub fn with_stream_transport(mut self, transport: StreamTransport) -> Self {
+        self.stream_transport = transport;
+        self
+    }
+
+    /// Ends a stream with [`AdapterError::StreamError`] ("timeout") when no event arrived for
+    /// `timeout`, e.g. because the connection stalled. The connection is closed in that case.
+    pub fn with_stream_idle_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.stream_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Keeps the last request envelope and response, see [`Self::last_exchange`].
+    pub fn with_debug_capture(mut self, enabled: bool) -> Self {
+        self.debug_capture = enabled.then(Default::default);
+        self
+    }
+
+    /// Server trace id of the last generation response, which Google support asks for in bug reports.
+    pub fn last_trace_id(&self) -> Option<String> {
+        self.last_trace_id.lock().unwrap().clone()
+    }
+
+    fn record_trace_id(slot: &std::sync::Mutex<Option<String>>, trace_id: Option<String>) {
+        let Some(trace_id) = trace_id else { return };
+        log::debug!("Response trace id: {}", trace_id);
+        *slot.lock().unwrap() = Some(trace_id);
+    }
+
+    /// The last exchange with the generate endpoints (inline data redacted), for bug reports.
+    /// `None` if nothing was sent yet or capture is disabled.
+    pub fn last_exchange(&self) -> Option<DebugExchange> {
+        self.debug_capture.as_ref()?.lock().unwrap().clone()
+    }
+
+    /// Stores an exchange if debug capture is enabled.
+    fn capture_exchange(
+        &self,
+        method: &str,
+        request: Option<serde_json::Value>,
+        started: (SystemTime, Instant),
+        response: Result<Option<&serde_json::Value>, &AdapterError>,
+    ) {
+        let (Some(slot), Some(request)) = (&self.debug_capture, request) else { return };
+
+        *slot.lock().unwrap() = Some(DebugExchange {
+            method: method.to_string(),
+            request,
+            response: response.ok().flatten().cloned(),
+            error: response.err().map(ToString::to_string),
+            started_at: started.0,
+            duration: started.1.elapsed(),
+        });
+    }
+
+    /// Installs a metrics sink that is notified about requests, latency and token usage.
+    pub fn with_metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    fn report_usage(metrics: &dyn Metrics, response: &GenerationResponse) {
+        if let Some(usage) = &response.usage_metadata {
+            metrics.on_tokens(
+                usage.prompt_token_count.unwrap_or(0).max(0) as u64,
+                usage.candidates_token_count.unwrap_or(0).max(0) as u64,
+            );
+        }
+    }
+
+    /// Wraps the request into a Code Assist envelope.
+    /// `cached_content` is forwarded as-is, but the Code Assist backend has no `cachedContents`
+    /// endpoint: there is no way to create such a cache for it, through this crate or otherwise.
+    fn build_envelope(&self, request: &GenerateContentRequest, model: &str) -> Result<CodeAssistEnvelope, AdapterError> {
+        let mut request_json = match self.thinking_budget {
+            Some(budget) => {
+                let mut request = request.clone();
+                let config = request.generation_config.get_or_insert_with(Default::default);
+                config
+                    .thinking_config
+                    .get_or_insert_with(Default::default)
+                    .thinking_budget = Some(budget);
+                serde_json::to_value(&request)?
+            }
+            None => serde_json::to_value(request)?,
+        };
+        let session_id = uuid::Uuid::new_v4().to_string();
+        if let Some(obj) = request_json.as_object_mut() {
+            obj.insert("session_id".to_string(), serde_json::json!(session_id));
+        }
+
+        Ok(CodeAssistEnvelope {
+            model: model.to_string(),
+            project: self.project_id(),
+            user_prompt_id: Some(
+                self.idempotency_key
+                    .clone()
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            ),
+            request: request_json,
+        })
+    }
+
+    /// Serializes the envelope for the request and checks it against the size limit.
+    fn encode_envelope(&self, request: &GenerateContentRequest, model: &str) -> Result<Vec<u8>, AdapterError> {
+        if self.project_id().is_empty() {
+            return Err(AdapterError::NoProject);
+        }
+
+        let envelope = self.build_envelope(request, model)?;
+        log::debug!("Sending Envelope: {}", serde_json::to_string_pretty(&envelope).unwrap());
+
+        let body = serde_json::to_vec(&envelope)?;
+        if body.len() > self.max_request_bytes {
+            return Err(AdapterError::ApiError {
+                code: 413,
+                status: None,
+                message: format!(
+                    "Request is too large ({} bytes, limit is {} bytes). Upload large files via the Files API instead of sending them inline.",
+                    body.len(),
+                    self.max_request_bytes
+                ),
+                details: Vec::new(),
+            });
+        }
+
+        Ok(body)
+    }
+
+    /// Performs a standard (non-streaming) request.
+    /// Accepts standard `GenerateContentRequest` from gemini-rust.
+    /// A blocked prompt or answer fails with [`AdapterError::ContentBlocked`], carrying the
+    /// reason and safety ratings.
+    pub async fn generate_content(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<GenerationResponse, AdapterError> {
+        if let Some(key) = &self.idempotency_key {
+            return self.generate_content_deduplicated(request, key).await;
+        }
+        Ok(self.generate_content_with_model(request).await?.0)
+    }
+
+    /// Same as [`Self::generate_content`] on a client with [`Self::with_idempotency_key`],
+    /// for a key that changes with every logical request.
+    pub async fn generate_content_idempotent(
+        &self,
+        request: &GenerateContentRequest,
+        key: &str,
+    ) -> Result<GenerationResponse, AdapterError> {
+        self.clone().with_idempotency_key(key).generate_content(request).await
+    }
+
+    /// In-flight deduplication behind [`Self::with_idempotency_key`].
+    async fn generate_content_deduplicated(
+        &self,
+        request: &GenerateContentRequest,
+        key: &str,
+    ) -> Result<GenerationResponse, AdapterError> {
+        let in_flight_key = format!("{}:{:x}", key, request_hash(request)?);
+
+        let joined = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&in_flight_key) {
+                Some(running) => Err(running.clone()),
+                None => {
+                    let (sender, receiver) = futures::channel::oneshot::channel();
+                    let shared = receiver
+                        .map(|result| result.unwrap_or_else(|_| Err(Arc::new(AdapterError::Cancelled))))
+                        .boxed()
+                        .shared();
+                    in_flight.insert(in_flight_key.clone(), shared);
+                    let guard = InFlightGuard { in_flight: self.in_flight.clone(), key: in_flight_key };
+                    Ok((sender, guard))
+                }
+            }
+        };
+        let (sender, _guard) = match joined {
+            Ok(leader) => leader,
+            Err(running) => {
+                log::debug!("Request with idempotency key {} already in flight, joining it", key);
+                return running.await.map_err(AdapterError::Deduplicated);
+            }
+        };
+
+        let result = self.generate_content_with_model(request).await.map(|(response, _)| response);
+
+        let shared = match &result {
+            Ok(response) => Ok(response.clone()),
+            Err(e) => Err(Arc::new(e.duplicate())),
+        };
+        // Nobody joined if the receiver is gone
+        let _ = sender.send(shared);
+        result
+    }
+
+    /// Same as [`Self::generate_content`], but also returns the billed token counts.
+    /// Counts the server didn't report are 0.
+    pub async fn generate_content_with_usage(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<(GenerationResponse, response::Usage), AdapterError> {
+        let response = self.generate_content(request).await?;
+        let usage = response::usage(&response).unwrap_or_else(|| {
+            log::debug!("Response has no usage metadata, reporting zero tokens");
+            Default::default()
+        });
+        Ok((response, usage))
+    }
+
+    /// Same as [`Self::generate_content`], but also returns the finish reason of the first
+    /// candidate and the usage, so callers can tell a complete answer from one cut off by
+    /// `MAX_TOKENS`. A blocked prompt or answer is not an error here: it is reported in
+    /// [`response::DetailedResponse::blocked`], with whatever the model produced before.
+    pub async fn generate_content_detailed(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<response::DetailedResponse, AdapterError> {
+        let (response, _) = self.generate_content_unchecked(request).await?;
+        Ok(response::DetailedResponse::new(response))
+    }
+
+    /// Same as [`Self::generate_content`], but also returns the model that answered,
+    /// which may be one of the fallbacks (see [`Self::with_model_fallbacks`]).
+    pub async fn generate_content_with_model(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<(GenerationResponse, String), AdapterError> {
+        let (response, model) = self.generate_content_unchecked(request).await?;
+        response::check_blocked(&response)?;
+        Ok((response, model))
+    }
+
+    /// Sends the request and decodes the response, without turning blocks into errors.
+    async fn generate_content_unchecked(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<(GenerationResponse, String), AdapterError> {
+        let (raw, model) = self.generate_content_raw_with_model(request).await?;
+        let envelope = serde_json::from_value::<CodeAssistResponseEnvelope>(raw)?;
+        Self::record_trace_id(&self.last_trace_id, envelope.trace_id);
+        let response = envelope
+            .response
+            .ok_or_else(|| <serde_json::Error as serde::de::Error>::missing_field("response"))?;
+
+        Self::report_usage(self.metrics.as_ref(), &response);
+        Ok((response, model))
+    }
+
+    /// Same as [`Self::generate_content`], but returns the whole response envelope as untyped JSON.
+    /// Escape hatch for fields that gemini-rust doesn't model yet.
+    pub async fn generate_content_raw(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<serde_json::Value, AdapterError> {
+        Ok(self.generate_content_raw_with_model(request).await?.0)
+    }
+
+    /// Runs the raw request through the model fallback chain.
+    async fn generate_content_raw_with_model(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<(serde_json::Value, String), AdapterError> {
+        let models = self.model_chain();
+        let last = models.len() - 1;
+        for (i, model) in models.into_iter().enumerate() {
+            match self.guarded(self.with_auth_retry(|| self.send_generate(request, &model))).await {
+                Err(e) if i < last && should_fall_back(&e) => {
+                    log::warn!("Model {} unavailable ({}), falling back to the next one", model, e);
+                }
+                result => return result.map(|raw| (raw, model)),
+            }
+        }
+        unreachable!("the model chain is never empty")
+    }
+
+    /// Runs a request through the circuit breaker, if one is configured.
+    async fn guarded<T>(
+        &self,
+        request: impl std::future::Future<Output = Result<T, AdapterError>>,
+    ) -> Result<T, AdapterError> {
+        let Some(breaker) = &self.circuit_breaker else { return request.await };
+
+        breaker.check()?;
+        let result = request.await;
+        breaker.record(&result);
+        result
+    }
+
+    /// Sends a single `generateContent` request for the given model.
+    async fn send_generate(
+        &self,
+        request: &GenerateContentRequest,
+        model: &str,
+    ) -> Result<serde_json::Value, AdapterError> {
+        self.credentials.ensure_valid().await?;
+        let body = self.encode_envelope(request, model)?;
+
+        let captured = self.debug_capture.as_ref().map(|_| debug::redacted_request(&body));
+        let started = (SystemTime::now(), Instant::now());
+        let result = self.post_generate(body.into()).await;
+        self.capture_exchange("generateContent", captured, started, result.as_ref().map(Some));
+        result
+    }
+
+    async fn post_generate(&self, body: Bytes) -> Result<serde_json::Value, AdapterError> {
+        let url = format!("{}:generateContent", self.base_url);
+
+        let mut attempt = 0;
+        let response = loop {
+            self.metrics.on_request_start("generateContent");
+            let started = Instant::now();
+            let request = self.http_client
+                .post(&url)
+                .query(&self.query_params)
+                .bearer_auth(self.credentials.token())
+                .header(reqwest::header::CONTENT_TYPE, "application/json");
+            let response = self.with_body(request, body.clone()).send().await;
+            let response = match response {
+                Ok(r) => r,
+                Err(e) => {
+                    self.metrics.on_request_end("generateContent", None, started.elapsed());
+                    return Err(e.into());
+                }
+            };
+
+            let status = response.status().as_u16();
+            if response.status().is_success() {
+                break (response, started);
+            }
+            self.metrics.on_request_end("generateContent", Some(status), started.elapsed());
+
+            match self.retry_policy {
+                Some(policy) if attempt < policy.max_retries && RetryPolicy::is_retryable(status) => {
+                    attempt += 1;
+                    let delay = policy.delay(attempt, retry::retry_after(response.headers()));
+                    log::warn!("generateContent failed with {}, retry {}/{} in {:?}", status, attempt, policy.max_retries, delay);
+                    self.metrics.on_retry("generateContent", attempt);
+                    tokio::time::sleep(delay).await;
+                }
+                _ => {
+                    return Err(AdapterError::from_response(response).await
+                        .with_quota_hint(&self.project_id())
+                        .with_service_disabled_hint(&self.project_id()));
+                }
+            }
+        };
+
+        let (response, started) = response;
+        if let Some(latency) = &self.latency {
+            latency.generate_first_byte.record(started.elapsed());
+        }
+        let status = response.status().as_u16();
+        let raw: Result<serde_json::Value, _> = response.json().await;
+        self.metrics.on_request_end("generateContent", Some(status), started.elapsed());
+        if let Some(latency) = &self.latency {
+            latency.generate_total.record(started.elapsed());
+        }
+
+        Ok(raw?)
+    }
+
+    /// Counts the tokens the request would consume with the configured model, without
+    /// running a generation. Useful to reject prompts that would exceed a budget.
+    pub async fn count_tokens(&self, request: &GenerateContentRequest) -> Result<usize, AdapterError> {
+        self.count_tokens_with_model(request, &self.resolve_model(&self.model())).await
+    }
+
+    async fn count_tokens_with_model(&self, request: &GenerateContentRequest, model: &str) -> Result<usize, AdapterError> {
+        self.credentials.ensure_valid().await?;
+        let url = format!("{}:countTokens", self.base_url);
+        let body = self.encode_envelope(request, model)?;
+
+        let response = self.http_client
+            .post(&url)
+            .bearer_auth(self.credentials.token())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(AdapterError::from_response(response).await
+                .with_service_disabled_hint(&self.project_id())
+                .context("Token count failed"));
+        }
+
+        let data: CountTokensResponse = response.json().await?;
+        Ok(data.total_tokens)
+    }
+
+    /// Preflight check with two cheap authenticated calls, to find out about a bad setup before
+    /// the first generation: the handshake checks the project, a token count of a one-word
+    /// prompt the model. Fails with
+    /// - [`AdapterError::AuthError`] if the token is invalid or expired,
+    /// - an `ApiError` for which [`AdapterError::is_service_disabled`] holds if the Code Assist
+    ///   API is not enabled for the project,
+    /// - [`AdapterError::ProjectNotFound`] if the project doesn't exist or the account can't
+    ///   use it, [`AdapterError::NoProject`] if there is none at all,
+    /// - [`AdapterError::ModelNotFound`] if Code Assist doesn't serve the configured model,
+    /// - the `ApiError` as-is otherwise, e.g. a 403 for a missing IAM role.
+    pub async fn verify(&self) -> Result<(), AdapterError> {
+        fn expired() -> AdapterError {
+            AdapterError::AuthError("the access token is invalid or expired".into())
+        }
+
+        match self.load_code_assist_full().await {
+            Ok(_) => {}
+            Err(e) if e.status_code() == Some(401) => return Err(expired()),
+            Err(e) if e.is_service_disabled() => return Err(e),
+            Err(e) if matches!(e.status(), Some(GoogleStatus::NotFound | GoogleStatus::PermissionDenied)) => {
+                return Err(AdapterError::ProjectNotFound(self.project_id()));
+            }
+            Err(e) => return Err(e),
+        }
+
+        let model = self.resolve_model(&self.model());
+        match self.count_tokens_with_model(&probe_request(), &model).await {
+            Ok(_) => Ok(()),
+            Err(e) if e.status_code() == Some(401) => Err(expired()),
+            Err(e) if e.is_model_not_found(&model) => Err(AdapterError::ModelNotFound(model)),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends all requests concurrently and returns the first successful response.
+    /// The remaining requests are cancelled. If every request fails, all errors are returned
+    /// in [`AdapterError::AllFailed`].
+    pub async fn generate_race(
+        &self,
+        requests: Vec<GenerateContentRequest>,
+    ) -> Result<GenerationResponse, AdapterError> {
+        let mut pending: FuturesUnordered<_> = requests
+            .iter()
+            .map(|request| self.generate_content(request))
+            .collect();
+
+        let mut errors = Vec::new();
+        while let Some(result) = pending.next().await {
+            match result {
+                Ok(response) => return Ok(response), // dropping `pending` aborts the losers
+                Err(e) => errors.push(e),
+            }
+        }
+
+        Err(AdapterError::AllFailed(errors))
+    }
+
+    /// Performs a streaming request.
+    /// Returns a stream of `GenerationResponse` (chunks).
+    pub async fn generate_content_stream(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<ResponseStream, AdapterError> {
+        Ok(self.generate_content_stream_with_model(request).await?.0)
+    }
+
+    /// Same as [`Self::generate_content_stream`], but also returns the model that answered,
+    /// which may be one of the fallbacks (see [`Self::with_model_fallbacks`]).
+    pub async fn generate_content_stream_with_model(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<(ResponseStream, String), AdapterError> {
+        let (stream, model) = self.open_stream_with_model::<CodeAssistResponseEnvelope>(request).await?;
+
+        // Usage metadata is cumulative across chunks, so only the last one is reported.
+        let metrics = self.metrics.clone();
+        let last_response: Arc<std::sync::Mutex<Option<GenerationResponse>>> = Default::default();
+        let last_response_tail = last_response.clone();
+        let last_trace_id = self.last_trace_id.clone();
+
+        let mapped_stream = stream
+        .map(move |item| {
+            match item {
+                Ok(envelope) => {
+                    Self::record_trace_id(&last_trace_id, envelope.trace_id);
+                    // Metadata-only / keepalive frames carry no response, skip them
+                    envelope.response.map(|resp| {
+                        response::check_prompt_blocked(&resp)?;
+                        Ok(resp)
+                    })
+                }
+                Err(e) => Some(Err(e)),
+            }
+        })
+        .filter_map(|x| async { x }) // Remove None
+        .inspect(move |item| match item {
+            Ok(resp) if resp.usage_metadata.is_some() => {
+                *last_response.lock().unwrap() = Some(resp.clone());
+            }
+            _ => {}
+        })
+        .chain(futures::stream::once(async move {
+            if let Some(resp) = last_response_tail.lock().unwrap().take() {
+                Self::report_usage(metrics.as_ref(), &resp);
+            }
+            None
+        }).filter_map(|x| async { x }));
+
+        Ok((Box::pin(mapped_stream), model))
+    }
+
+    /// Like [`Self::generate_content_stream`], but always uses the JSON array transport
+    /// ([`StreamTransport::JsonArray`]) for this request, for networks that mangle SSE.
+    pub async fn generate_content_stream_json(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<ResponseStream, AdapterError> {
+        let client = self.clone().with_stream_transport(StreamTransport::JsonArray);
+        client.generate_content_stream(request).await
+    }
+
+    /// Like [`Self::generate_content_stream`], but without the usage-only chunks (no candidates)
+    /// the backend sometimes sends last. Their usage is collected in the returned [`response::StreamUsage`].
+    pub async fn generate_content_stream_with_usage(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<(ResponseStream, response::StreamUsage), AdapterError> {
+        let stream = self.generate_content_stream(request).await?;
+        let usage = response::StreamUsage::default();
+        let tracker = usage.clone();
+
+        let content_only = stream.filter(move |item| {
+            let keep = match item {
+                Ok(resp) => {
+                    tracker.push(resp);
+                    !resp.candidates.is_empty() || resp.prompt_feedback.is_some()
+                }
+                Err(_) => true,
+            };
+            futures::future::ready(keep)
+        });
+
+        Ok((Box::pin(content_only), usage))
+    }
+
+    /// Like [`Self::generate_content_stream`], but decodes every SSE event as `E` instead of
+    /// [`CodeAssistResponseEnvelope`]. For backend variants with a different envelope shape, or
+    /// to keep per-chunk fields such as `trace_id`. Events are passed through as-is: no
+    /// safety checks, no usage metrics.
+    pub async fn generate_content_stream_as<E>(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<E, AdapterError>> + Send>>, AdapterError>
+    where
+        E: serde::de::DeserializeOwned + Send + 'static,
+    {
+        Ok(self.open_stream_with_model(request).await?.0)
+    }
+
+    /// Runs the stream request through the model fallback chain.
+    async fn open_stream_with_model<E>(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<(Pin<Box<dyn Stream<Item = Result<E, AdapterError>> + Send>>, String), AdapterError>
+    where
+        E: serde::de::DeserializeOwned + Send + 'static,
+    {
+        let models = self.model_chain();
+        let last = models.len() - 1;
+        for (i, model) in models.into_iter().enumerate() {
+            match self.guarded(self.with_auth_retry(|| self.open_stream(request, &model))).await {
+                Err(e) if i < last && should_fall_back(&e) => {
+                    log::warn!("Model {} unavailable ({}), falling back to the next one", model, e);
+                }
+                Err(e) => return Err(e),
+                Ok(events) => {
+                    let decoded = events.map(|data| Ok(serde_json::from_str::<E>(&data?)?));
+                    return Ok((Box::pin(decoded), model));
+                }
+            }
+        }
+        unreachable!("the model chain is never empty")
+    }
+
+    /// Opens a single `streamGenerateContent` request for the given model and yields the raw
+    /// data of its SSE events.
+    async fn open_stream(
+        &self,
+        request: &GenerateContentRequest,
+        model: &str,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, AdapterError>> + Send>>, AdapterError> {
+        self.credentials.ensure_valid().await?;
+        let body = self.encode_envelope(request, model)?;
+
+        let captured = self.debug_capture.as_ref().map(|_| debug::redacted_request(&body));
+        let started = (SystemTime::now(), Instant::now());
+        let result = self.post_stream(body.into()).await;
+        self.capture_exchange("streamGenerateContent", captured, started, result.as_ref().map(|_| None));
+        result
+    }
+
+    async fn post_stream(
+        &self,
+        body: Bytes,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, AdapterError>> + Send>>, AdapterError> {
+        let url = format!("{}:streamGenerateContent", self.base_url);
+
+        self.metrics.on_request_start("streamGenerateContent");
+        let started = Instant::now();
+        let request = self.http_client
+            .post(&url)
+            .query(match self.stream_transport {
+                StreamTransport::Sse => &[("alt", "sse")][..],
+                StreamTransport::JsonArray => &[][..],
+            })
+            .query(&self.query_params)
+            .bearer_auth(self.credentials.token())
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        let response = self.with_body(request, body).send().await;
+        let response = match response {
+            Ok(r) => r,
+            Err(e) => {
+                self.metrics.on_request_end("streamGenerateContent", None, started.elapsed());
+                return Err(e.into());
+            }
+        };
+
+        // For streams the latency is measured up to the response headers (time to first byte)
+        self.metrics.on_request_end("streamGenerateContent", Some(response.status().as_u16()), started.elapsed());
+        if let Some(latency) = &self.latency {
+            latency.stream_first_byte.record(started.elapsed());
+        }
+
+        if !response.status().is_success() {
+            return Err(AdapterError::from_response(response).await
+                .with_quota_hint(&self.project_id())
+                .with_service_disabled_hint(&self.project_id()));
+        }
+
+        let events: Pin<Box<dyn Stream<Item = Result<String, AdapterError>> + Send>> = match self.stream_transport {
+            StreamTransport::JsonArray => {
+                let mut splitter = JsonArraySplitter::default();
+                // `None` marks the end of the body, where the array must be complete
+                let elements = response.bytes_stream()
+                    .map(Some)
+                    .chain(futures::stream::once(futures::future::ready(None)))
+                    .map(move |chunk| {
+                        let elements = match chunk {
+                            Some(Ok(bytes)) => splitter.push(&bytes),
+                            Some(Err(e)) => vec![Err(AdapterError::StreamError(Box::new(e)))],
+                            None => splitter.finish().err().map(Err).into_iter().collect(),
+                        };
+                        futures::stream::iter(elements)
+                    })
+                    .flatten();
+                Box::pin(elements)
+            }
+            StreamTransport::Sse => {
+                // The sentinel ends the stream; without it the stream simply ends when the connection closes.
+                let done_sentinel = self.done_sentinel.clone();
+                let events = response.bytes_stream().eventsource()
+                .take_while(move |event_result| {
+                    let done = matches!(event_result, Ok(event) if is_done_sentinel(&event.data, &done_sentinel));
+                    async move { !done }
+                })
+                .map(|event_result| match event_result {
+                    Ok(event) => Ok(event.data),
+                    Err(e) => Err(AdapterError::StreamError(Box::new(e))),
+                });
+                Box::pin(events)
+            }
+        };
+
+        let events: Pin<Box<dyn Stream<Item = Result<String, AdapterError>> + Send>> = match self.stream_idle_timeout {
+            Some(timeout) => Box::pin(stream::idle_timeout(events, timeout)),
+            None => events,
+        };
+
+        match self.latency.clone() {
+            Some(latency) => Ok(Box::pin(stream::on_end(events, move || latency.stream_total.record(started.elapsed())))),
+            None => Ok(events),
+        }
+    }
+
+    /// Like [`Self::generate_content`], but gives up with [`AdapterError::Timeout`] once
+    /// `deadline` has passed. The deadline is absolute and covers model fallbacks as well.
+    pub async fn generate_content_with_deadline(
+        &self,
+        request: &GenerateContentRequest,
+        deadline: std::time::Instant,
+    ) -> Result<GenerationResponse, AdapterError> {
+        tokio::time::timeout_at(deadline.into(), self.generate_content(request))
+            .await
+            .map_err(|_| AdapterError::Timeout)?
+    }
+
+    /// Like [`Self::generate_content_stream`], but both opening the stream and every chunk
+    /// are bounded by `deadline`. Once it has passed, the stream yields
+    /// [`AdapterError::Timeout`] and ends.
+    pub async fn generate_content_stream_with_deadline(
+        &self,
+        request: &GenerateContentRequest,
+        deadline: std::time::Instant,
+    ) -> Result<ResponseStream, AdapterError> {
+        let deadline = tokio::time::Instant::from_std(deadline);
+        let stream = tokio::time::timeout_at(deadline, self.generate_content_stream(request))
+            .await
+            .map_err(|_| AdapterError::Timeout)??;
+
+        let bounded = futures::stream::unfold(Some(stream), move |stream| async move {
+            let mut stream = stream?;
+            match tokio::time::timeout_at(deadline, stream.next()).await {
+                Ok(Some(item)) => Some((item, Some(stream))),
+                Ok(None) => None,
+                Err(_) => Some((Err(AdapterError::Timeout), None)),
+            }
+        });
+
+        Ok(Box::pin(bounded))
+    }
+
+    /// Like [`Self::generate_content_stream`], but yields typed events (answer text, thoughts,
+    /// function calls) and a final [`GenerationEvent::Done`], see [`stream::generation_events`].
+    pub async fn generate_content_events(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<GenerationEvent, AdapterError>> + Send>>, AdapterError> {
+        let stream = self.generate_content_stream(request).await?;
+        Ok(Box::pin(stream::generation_events(stream)))
+    }
+
+    /// Like [`Self::generate_content_stream`], but aborts as soon as `cancel` is triggered:
+    /// the stream yields [`AdapterError::Cancelled`] and ends, closing the connection.
+    /// Simply dropping the stream closes the connection as well.
+    pub async fn generate_content_stream_with_cancel(
+        &self,
+        request: &GenerateContentRequest,
+        cancel: CancellationToken,
+    ) -> Result<ResponseStream, AdapterError> {
+        let stream = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => return Err(AdapterError::Cancelled),
+            stream = self.generate_content_stream(request) => stream?,
+        };
+
+        let cancellable = futures::stream::unfold(Some(stream), move |stream| {
+            let cancel = cancel.clone();
+            async move {
+                let mut stream = stream?;
+                tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => Some((Err(AdapterError::Cancelled), None)),
+                    item = stream.next() => item.map(|item| (item, Some(stream))),
+                }
+            }
+        });
+
+        Ok(Box::pin(cancellable))
+    }
+
+    /// Like [`Self::generate_content_stream`], but with auto-resume enabled (see
+    /// [`Self::with_stream_auto_resume`]) a connection drop mid-stream re-issues the request
+    /// with the partial answer appended and keeps streaming. Callers are told via
+    /// [`StreamEvent::Reconnected`]; the chunks before and after it form one answer.
+    pub async fn generate_content_stream_resumable(
+        &self,
+        request: &GenerateContentRequest,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamEvent, AdapterError>> + Send>>, AdapterError> {
+        struct State {
+            client: CodeAssistClient,
+            request: GenerateContentRequest,
+            stream: Option<ResponseStream>,
+            received: String,
+            attempts: u32,
+        }
+
+        let state = State {
+            client: self.clone(),
+            request: request.clone(),
+            stream: Some(self.generate_content_stream(request).await?),
+            received: String::new(),
+            attempts: 0,
+        };
+
+        let events = futures::stream::unfold(state, |mut state| async move {
+            let item = state.stream.as_mut()?.next().await?;
+            match item {
+                Ok(chunk) => {
+                    state.received.push_str(&chunk.text());
+                    Some((Ok(StreamEvent::Chunk(chunk)), state))
+                }
+                Err(e) if state.client.stream_auto_resume
+                    && state.attempts < STREAM_RESUME_MAX_ATTEMPTS
+                    && matches!(e, AdapterError::StreamError(_) | AdapterError::RequestFailed(_)) =>
+                {
+                    state.attempts += 1;
+                    log::warn!("Stream dropped ({}), resuming (attempt {}/{})", e, state.attempts, STREAM_RESUME_MAX_ATTEMPTS);
+
+                    let mut resume = state.request.clone();
+                    resume.contents.push(Content::text(state.received.clone()).with_role(Role::Model));
+                    resume.contents.push(Content::text(STREAM_RESUME_PROMPT).with_role(Role::User));
+
+                    match state.client.generate_content_stream(&resume).await {
+                        Ok(stream) => {
+                            state.stream = Some(stream);
+                            let attempt = state.attempts;
+                            Some((Ok(StreamEvent::Reconnected { attempt }), state))
+                        }
+                        Err(e) => {
+                            state.stream = None;
+                            Some((Err(e), state))
+                        }
+                    }
+                }
+                Err(e) => {
+                    state.stream = None;
+                    Some((Err(e), state))
+                }
+            }
+        });
+
+        Ok(Box::pin(events))
+    }
+
+    /// Runs a streaming request and forwards every chunk to `tx`.
+    ///
+    /// Waits for channel capacity (backpressure) and stops early if the receiver is dropped.
+    /// Failing to start the stream is returned directly, errors mid-stream are sent to the channel.
+    pub async fn generate_content_to_channel(
+        &self,
+        request: &GenerateContentRequest,
+        tx: tokio::sync::mpsc::Sender<Result<GenerationResponse, AdapterError>>,
+    ) -> Result<(), AdapterError> {
+        let mut stream = self.generate_content_stream(request).await?;
+
+        while let Some(item) = stream.next().await {
+            if tx.send(item).await.is_err() {
+                log::debug!("Receiver dropped, stopping stream");
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Object-safe view of a text generation backend.
+///
+/// Lets application code depend on `Arc<dyn GenerativeClient>` instead of [`CodeAssistClient`],
+/// so it can be swapped for a mock in tests.
+pub trait GenerativeClient: Send + Sync {
+    fn generate_content<'a>(
+        &'a self,
+        request: &'a GenerateContentRequest,
+    ) -> BoxFuture<'a, Result<GenerationResponse, AdapterError>>;
+
+    fn generate_content_stream<'a>(
+        &'a self,
+        request: &'a GenerateContentRequest,
+    ) -> BoxFuture<'a, Result<ResponseStream, AdapterError>>;
+}
+
+impl GenerativeClient for CodeAssistClient {
+    fn generate_content<'a>(
+        &'a self,
+        request: &'a GenerateContentRequest,
+    ) -> BoxFuture<'a, Result<GenerationResponse, AdapterError>> {
+        CodeAssistClient::generate_content(self, request).boxed()
+    }
+
+    fn generate_content_stream<'a>(
+        &'a self,
+        request: &'a GenerateContentRequest,
+    ) -> BoxFuture<'a, Result<ResponseStream, AdapterError>> {
+        CodeAssistClient::generate_content_stream(self, request).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn done_sentinel_matches_exactly() {
+        assert!(is_done_sentinel("[DONE]", DEFAULT_DONE_SENTINEL));
+        assert!(!is_done_sentinel(r#"{"response": {}}"#, DEFAULT_DONE_SENTINEL));
+        assert!(!is_done_sentinel("", DEFAULT_DONE_SENTINEL));
+    }
+
+    #[test]
+    fn done_sentinel_tolerates_whitespace_prefix_and_case() {
+        assert!(is_done_sentinel("  [DONE]\r\n", DEFAULT_DONE_SENTINEL));
+        assert!(is_done_sentinel("data: [DONE]", DEFAULT_DONE_SENTINEL));
+        assert!(is_done_sentinel("[done]", DEFAULT_DONE_SENTINEL));
+        assert!(is_done_sentinel("END", " END "));
+    }
+
+    #[test]
+    fn best_channel_match_prefers_newest_then_stable() {
+        let models = ["gemini-2.0-flash", "gemini-2.5-flash", "gemini-2.5-flash-preview", "gemini-2.5-flash-lite", "gemini-2.5-pro"];
+        assert_eq!(best_channel_match("flash-latest", &models).as_deref(), Some("gemini-2.5-flash"));
+        assert_eq!(best_channel_match("pro-latest", &models).as_deref(), Some("gemini-2.5-pro"));
+        assert_eq!(best_channel_match("flash", &models).as_deref(), Some("gemini-2.5-flash"));
+
+        let previews = ["gemini-2.5-flash", "gemini-3-flash-preview", "gemini-3-flash-preview-05-20"];
+        assert_eq!(best_channel_match("flash-latest", &previews).as_deref(), Some("gemini-3-flash-preview"));
+        assert_eq!(best_channel_match("ultra-latest", &previews), None);
+    }
+
+    #[test]
+    fn sanitize_model_name_forms() {
+        assert_eq!(sanitize_model_name("gemini-2.5-flash"), "gemini-2.5-flash");
+        assert_eq!(sanitize_model_name("models/gemini-2.5-flash"), "gemini-2.5-flash");
+        assert_eq!(sanitize_model_name("projects/my-project/locations/us-central1/models/gemini-2.5-flash"), "gemini-2.5-flash");
+        assert_eq!(sanitize_model_name(" models/gemini-2.5-flash\n"), "gemini-2.5-flash");
+    }
+
+    #[test]
+    fn sanitize_model_name_is_idempotent() {
+        for model in ["gemini-2.5-flash", "models/gemini-2.5-flash", "projects/p/locations/l/models/gemini-2.5-flash"] {
+            let once = sanitize_model_name(model);
+            assert_eq!(sanitize_model_name(&once), once);
+        }
+    }
+
+    #[test]
+    fn with_model_stores_bare_name() {
+        let client = CodeAssistClient::new("token".into(), "project".into()).with_model("models/gemini-2.0-flash");
+        assert_eq!(client.model(), "gemini-2.0-flash");
+    }
+
+    #[tokio::test]
+    async fn token_expiry_does_not_leak_into_clones() {
+        let client = CodeAssistClient::new("token".into(), "project".into());
+        let expired = client.clone().with_token_expiry(SystemTime::UNIX_EPOCH);
+
+        assert!(matches!(expired.credentials.ensure_valid().await, Err(AdapterError::AuthError(_))));
+        assert!(client.credentials.ensure_valid().await.is_ok());
+    }
+
+    fn envelope_json(client: &CodeAssistClient, request: &GenerateContentRequest) -> serde_json::Value {
+        serde_json::to_value(client.build_envelope(request, "gemini-2.5-flash").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn thinking_budget_lands_in_envelope() {
+        let client = CodeAssistClient::new("token".into(), "project".into()).with_thinking_budget(512);
+        let json = envelope_json(&client, &request::RequestBuilder::new().user("hi").build());
+
+        assert_eq!(json.pointer("/request/generationConfig/thinkingConfig/thinkingBudget"), Some(&serde_json::json!(512)));
+        assert_eq!(json["model"], "gemini-2.5-flash");
+        assert_eq!(json["project"], "project");
+    }
+
+    #[test]
+    fn thinking_budget_overrides_request() {
+        let client = CodeAssistClient::new("token".into(), "project".into()).with_thinking_budget(0);
+        let mut request = request::RequestBuilder::new().user("hi").build();
+        request.generation_config.get_or_insert_with(Default::default).thinking_config =
+            Some(serde_json::from_value(serde_json::json!({"thinkingBudget": 1024, "includeThoughts": true})).unwrap());
+        let json = envelope_json(&client, &request);
+
+        assert_eq!(json.pointer("/request/generationConfig/thinkingConfig/thinkingBudget"), Some(&serde_json::json!(0)));
+        assert_eq!(json.pointer("/request/generationConfig/thinkingConfig/includeThoughts"), Some(&serde_json::json!(true)));
+    }
+
+    #[test]
+    fn no_thinking_config_without_budget() {
+        let client = CodeAssistClient::new("token".into(), "project".into());
+        let json = envelope_json(&client, &request::RequestBuilder::new().user("hi").build());
 
-        Ok(Box::pin(mapped_stream))
+        let budget = json.pointer("/request/generationConfig/thinkingConfig/thinkingBudget");
+        assert!(budget.is_none_or(serde_json::Value::is_null));
     }
 }
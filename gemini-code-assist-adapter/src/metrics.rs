@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+/// Hooks for exporting client metrics (Prometheus, OpenTelemetry, etc.).
+///
+/// All methods have empty default implementations, so an implementor only
+/// needs to override the callbacks it cares about.
+pub trait Metrics: Send + Sync {
+    /// Called right before a request is sent. `method` is the API method name (e.g. "generateContent").
+    fn on_request_start(&self, _method: &str) {}
+
+    /// Called once the server has answered (or the request failed).
+    /// `status` is `None` if no HTTP response was received at all.
+    fn on_request_end(&self, _method: &str, _status: Option<u16>, _latency: Duration) {}
+
+    /// Called with the token usage reported by the server for a finished generation.
+    fn on_tokens(&self, _prompt: u64, _candidates: u64) {}
+
+    /// Called before a request is retried. `attempt` starts at 1 for the first retry.
+    fn on_retry(&self, _method: &str, _attempt: u32) {}
+}
+
+/// Default implementation that does nothing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {}
+
+/// Growth factor between histogram buckets, i.e. percentiles are accurate to about 10%.
+const BUCKET_GROWTH: f64 = 1.1;
+/// Enough buckets to cover latencies of a couple of days.
+const BUCKET_COUNT: usize = 200;
+
+/// Simple bucketed latency histogram, see [`crate::CodeAssistClient::with_latency_tracking`].
+#[derive(Debug)]
+pub struct LatencyStats {
+    buckets: std::sync::Mutex<Vec<u64>>,
+}
+
+impl Default for LatencyStats {
+    fn default() -> Self {
+        Self {
+            buckets: std::sync::Mutex::new(vec![0; BUCKET_COUNT]),
+        }
+    }
+}
+
+/// Latency histograms per method and phase, see [`crate::CodeAssistClient::with_latency_tracking`].
+/// Every phase is measured from the start of the request (of the successful attempt, if retried).
+///
+/// There is no connect phase: the HTTP client doesn't report connection setup separately, and
+/// reuses pooled connections. It is part of the time to first byte of requests that needed one.
+#[derive(Debug, Default)]
+pub struct RequestLatencies {
+    /// `generateContent`, until the response headers arrived (time to first byte).
+    /// The backend sends them once the answer is generated, so this is close to the total.
+    pub generate_first_byte: LatencyStats,
+    /// `generateContent`, until the whole response body was received.
+    pub generate_total: LatencyStats,
+    /// `streamGenerateContent`, until the response headers arrived (time to first byte).
+    pub stream_first_byte: LatencyStats,
+    /// `streamGenerateContent`, until the stream ended. Streams dropped early are not recorded.
+    pub stream_total: LatencyStats,
+}
+
+impl RequestLatencies {
+    pub fn reset(&self) {
+        self.generate_first_byte.reset();
+        self.generate_total.reset();
+        self.stream_first_byte.reset();
+        self.stream_total.reset();
+    }
+}
+
+impl LatencyStats {
+    pub fn record(&self, latency: Duration) {
+        let millis = latency.as_secs_f64() * 1000.0;
+        let index = ((millis + 1.0).ln() / BUCKET_GROWTH.ln()) as usize;
+        self.buckets.lock().unwrap()[index.min(BUCKET_COUNT - 1)] += 1;
+    }
+
+    /// Number of recorded requests.
+    pub fn count(&self) -> u64 {
+        self.buckets.lock().unwrap().iter().sum()
+    }
+
+    pub fn p50(&self) -> Option<Duration> {
+        self.percentile(0.50)
+    }
+
+    pub fn p90(&self) -> Option<Duration> {
+        self.percentile(0.90)
+    }
+
+    pub fn p99(&self) -> Option<Duration> {
+        self.percentile(0.99)
+    }
+
+    /// Upper bound of the bucket containing the given quantile (0.0..=1.0).
+    /// `None` if nothing was recorded yet.
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        let buckets = self.buckets.lock().unwrap();
+        let total: u64 = buckets.iter().sum();
+        if total == 0 {
+            return None;
+        }
+
+        let rank = ((total as f64 * quantile).ceil() as u64).max(1);
+        let mut seen = 0;
+        let index = buckets.iter().position(|&n| {
+            seen += n;
+            seen >= rank
+        })?;
+
+        let upper_millis = BUCKET_GROWTH.powi(index as i32 + 1) - 1.0;
+        Some(Duration::from_secs_f64(upper_millis / 1000.0))
+    }
+
+    pub fn reset(&self) {
+        self.buckets.lock().unwrap().fill(0);
+    }
+}
@@ -14,11 +14,41 @@ pub struct CodeAssistEnvelope {
 
 #[derive(Debug, Deserialize)]
 pub struct CodeAssistResponseEnvelope {
-    pub response: GenerationResponse,
+    /// Absent on metadata-only frames.
+    pub response: Option<GenerationResponse>,
+    #[serde(alias = "traceId")]
     pub trace_id: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CountTokensResponse {
+    #[serde(default)]
+    pub total_tokens: usize,
+}
+
 // Structures for handshake (kept as they were in the previous response)
+/// gemini-cli version reported in the handshake metadata by default.
+///
+/// Known-good: 0.21.0. Bump this when the backend starts rejecting older CLI versions;
+/// it can also be overridden per client with `with_ide_version` / `with_plugin_version`.
+pub const DEFAULT_CLI_VERSION: &str = "0.21.0";
+
+/// IDE type reported in the handshake metadata by default.
+pub const DEFAULT_IDE_TYPE: &str = "GEMINI_CLI";
+
+/// Platform of this build as named by the API, e.g. `DARWIN_ARM64`.
+pub fn current_platform() -> &'static str {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => "LINUX_AMD64",
+        ("linux", "aarch64") => "LINUX_ARM64",
+        ("macos", "x86_64") => "DARWIN_AMD64",
+        ("macos", "aarch64") => "DARWIN_ARM64",
+        ("windows", "x86_64") => "WINDOWS_AMD64",
+        _ => "PLATFORM_UNSPECIFIED",
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ClientMetadata {
@@ -32,10 +62,10 @@ pub struct ClientMetadata {
 impl Default for ClientMetadata {
     fn default() -> Self {
         Self {
-            ide_type: "GEMINI_CLI".to_string(),
-            ide_version: "0.21.0".to_string(),
-            plugin_version: "0.21.0".to_string(),
-            platform: "LINUX_AMD64".to_string(),
+            ide_type: DEFAULT_IDE_TYPE.to_string(),
+            ide_version: DEFAULT_CLI_VERSION.to_string(),
+            plugin_version: DEFAULT_CLI_VERSION.to_string(),
+            platform: current_platform().to_string(),
             plugin_type: "GEMINI".to_string(),
         }
     }
@@ -54,11 +84,74 @@ pub struct LoadCodeAssistRequest {
 pub struct LoadCodeAssistResponse {
     pub cloudaicompanion_project: Option<String>,
     pub current_tier: Option<Tier>,
+    pub allowed_tiers: Option<Vec<Tier>>,
 }
 
-#[derive(Debug, Deserialize)]
+/// Well-known onboarding tiers, see `CodeAssistClient::with_tier`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TierKind {
+    #[default]
+    Free,
+    /// Gemini Code Assist Standard subscription.
+    Standard,
+    /// Gemini Code Assist Enterprise subscription.
+    Enterprise,
+}
+
+impl TierKind {
+    /// The `tierId` the API expects.
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::Free => "free-tier",
+            Self::Standard => "standard-tier",
+            Self::Enterprise => "enterprise-tier",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Tier {
     pub id: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub is_default: bool,
+    /// Whether the user has to bring their own Cloud project for this tier.
+    pub user_defined_cloudaicompanion_project: Option<bool>,
+    pub privacy_notice: Option<PrivacyNotice>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrivacyNotice {
+    #[serde(default)]
+    pub show_notice: bool,
+    pub notice_text: Option<String>,
+}
+
+/// Everything the handshake (`loadCodeAssist`) told us about the user.
+#[derive(Debug, Clone)]
+pub struct HandshakeResult {
+    /// Project to use for further requests.
+    pub project_id: String,
+    /// Tier the user is already onboarded into, if any.
+    pub current_tier: Option<Tier>,
+    /// Tiers the user is allowed to onboard into.
+    pub allowed_tiers: Vec<Tier>,
+}
+
+impl HandshakeResult {
+    /// Tier to onboard into: the one flagged as default, or the only allowed one.
+    pub fn onboarding_tier(&self) -> Option<&Tier> {
+        self.allowed_tiers
+            .iter()
+            .find(|t| t.is_default)
+            .or(match self.allowed_tiers.as_slice() {
+                [only] => Some(only),
+                _ => None,
+            })
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -72,9 +165,28 @@ pub struct OnboardUserRequest {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LroResponse {
+    #[serde(default)]
     pub name: String,
     pub done: Option<bool>,
     pub response: Option<OnboardUserResponse>,
+    /// Set when the operation finished with a failure.
+    pub error: Option<LroStatus>,
+}
+
+/// `google.rpc.Status` of a failed operation.
+#[derive(Debug, Deserialize)]
+pub struct LroStatus {
+    #[serde(default)]
+    pub code: i32,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl LroResponse {
+    /// Project confirmed by a finished operation, if any.
+    pub fn project_id(&self) -> Option<&str> {
+        self.response.as_ref()?.cloudaicompanion_project.as_ref().map(|p| p.id.as_str())
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -87,3 +199,65 @@ pub struct OnboardUserResponse {
 pub struct ProjectInfo {
     pub id: String,
 }
+
+/// A model Code Assist is known to serve, see [`KNOWN_MODELS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelInfo {
+    /// Name without the `models/` prefix, e.g. `gemini-2.5-flash`.
+    pub name: &'static str,
+    /// e.g. `generateContent`, `countTokens`.
+    pub supported_generation_methods: &'static [&'static str],
+    pub input_token_limit: u32,
+    pub output_token_limit: u32,
+}
+
+impl ModelInfo {
+    pub fn supports_generation(&self) -> bool {
+        self.supported_generation_methods.contains(&"generateContent")
+    }
+}
+
+const CODE_ASSIST_METHODS: &[&str] = &["generateContent", "streamGenerateContent", "countTokens"];
+
+const fn known_model(name: &'static str, output_token_limit: u32) -> ModelInfo {
+    ModelInfo {
+        name,
+        supported_generation_methods: CODE_ASSIST_METHODS,
+        input_token_limit: 1_048_576,
+        output_token_limit,
+    }
+}
+
+/// Models the Code Assist backend served at the time of writing. The backend has no model
+/// listing of its own, so this list is curated by hand; which of them an account can actually
+/// use is checked with [`crate::CodeAssistClient::verify_model`].
+pub const KNOWN_MODELS: &[ModelInfo] = &[
+    known_model("gemini-3-pro-preview", 65_536),
+    known_model("gemini-3-flash-preview", 65_536),
+    known_model("gemini-2.5-pro", 65_536),
+    known_model("gemini-2.5-flash", 65_536),
+    known_model("gemini-2.5-flash-lite", 65_536),
+    known_model("gemini-2.0-flash", 8_192),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_only_envelope_has_no_response() {
+        let envelope: CodeAssistResponseEnvelope = serde_json::from_str(r#"{"traceId": "abc123"}"#).unwrap();
+        assert!(envelope.response.is_none());
+        assert_eq!(envelope.trace_id.as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn envelope_with_response() {
+        let envelope: CodeAssistResponseEnvelope = serde_json::from_str(
+            r#"{"response": {"candidates": [{"content": {"role": "model", "parts": [{"text": "hi"}]}}]}, "trace_id": "t"}"#,
+        )
+        .unwrap();
+        assert_eq!(envelope.response.unwrap().text(), "hi");
+        assert_eq!(envelope.trace_id.as_deref(), Some("t"));
+    }
+}
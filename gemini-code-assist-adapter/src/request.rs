@@ -0,0 +1,96 @@
+//! Builder for `GenerateContentRequest`s, for the common knobs without spelling out every field.
+
+use gemini_rust::{Content, GenerateContentRequest, GenerationConfig, Role};
+
+#[derive(Debug, Clone, Default)]
+pub struct RequestBuilder {
+    contents: Vec<Content>,
+    system_instruction: Option<Content>,
+    config: GenerationConfig,
+}
+
+impl RequestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a user turn.
+    pub fn user(mut self, text: impl Into<String>) -> Self {
+        self.contents.push(Content::text(text).with_role(Role::User));
+        self
+    }
+
+    /// Appends a model turn (e.g. a previous answer from the history).
+    pub fn model(mut self, text: impl Into<String>) -> Self {
+        self.contents.push(Content::text(text).with_role(Role::Model));
+        self
+    }
+
+    /// Appends an arbitrary content, e.g. one with inline data.
+    pub fn content(mut self, content: Content) -> Self {
+        self.contents.push(content);
+        self
+    }
+
+    pub fn system_instruction(mut self, text: impl Into<String>) -> Self {
+        self.system_instruction = Some(Content::text(text));
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.config.temperature = Some(temperature);
+        self
+    }
+
+    pub fn max_output_tokens(mut self, max_output_tokens: i32) -> Self {
+        self.config.max_output_tokens = Some(max_output_tokens);
+        self
+    }
+
+    /// Generation stops at the first occurrence of any of these (sent as `stopSequences`).
+    pub fn stop_sequences(mut self, stop_sequences: Vec<impl Into<String>>) -> Self {
+        self.config.stop_sequences = Some(stop_sequences.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Number of alternative responses to generate (sent as `candidateCount`).
+    pub fn candidate_count(mut self, candidate_count: i32) -> Self {
+        self.config.candidate_count = Some(candidate_count);
+        self
+    }
+
+    pub fn build(self) -> GenerateContentRequest {
+        GenerateContentRequest {
+            contents: self.contents,
+            generation_config: Some(self.config),
+            safety_settings: None,
+            tools: None,
+            tool_config: None,
+            system_instruction: self.system_instruction,
+            cached_content: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_json(request: GenerateContentRequest) -> serde_json::Value {
+        serde_json::to_value(request).unwrap()["generationConfig"].clone()
+    }
+
+    #[test]
+    fn stop_sequences_serialize_camel_case() {
+        let config = config_json(RequestBuilder::new().user("hi").stop_sequences(vec!["END", "\n\n"]).build());
+        assert_eq!(config["stopSequences"], serde_json::json!(["END", "\n\n"]));
+        assert!(config.get("stop_sequences").is_none());
+    }
+
+    #[test]
+    fn candidate_count_serializes_camel_case() {
+        let config = config_json(RequestBuilder::new().user("hi").candidate_count(3).build());
+        assert_eq!(config["candidateCount"], serde_json::json!(3));
+        assert!(config.get("candidate_count").is_none());
+    }
+}
@@ -0,0 +1,320 @@
+//! Helpers for reading `GenerationResponse`s returned by the client.
+
+use gemini_rust::{Candidate, Content, FinishReason, FunctionCall, FunctionResponse, GenerationResponse, Part, PromptFeedback, Role, SafetyRating, UsageMetadata};
+use crate::error::{AdapterError, BlockedPart};
+
+/// Index of a candidate within a response. Falls back to its position when the server omits it.
+fn candidate_index(candidate: &Candidate, position: usize) -> usize {
+    candidate.index.map(|i| i as usize).unwrap_or(position)
+}
+
+/// Concatenated (non-thought) text of a single candidate.
+fn candidate_text(candidate: &Candidate) -> String {
+    let mut text = String::new();
+    for part in candidate.content.parts.iter().flatten() {
+        match part {
+            Part::Text { text: t, thought, .. } if !thought.unwrap_or(false) => text.push_str(t),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Returns the text of every candidate, ordered by candidate index.
+/// Useful with `candidate_count > 1`, since `GenerationResponse::text()` only looks at the first one.
+pub fn candidates_text(response: &GenerationResponse) -> Vec<String> {
+    let mut acc = CandidateAccumulator::default();
+    acc.push(response);
+    acc.into_texts()
+}
+
+/// Accumulates streamed chunks per candidate index.
+///
+/// With multiple candidates the server tags each chunk with the candidate it belongs to,
+/// and chunks of different candidates may arrive interleaved.
+#[derive(Debug, Default, Clone)]
+pub struct CandidateAccumulator {
+    texts: Vec<String>,
+}
+
+impl CandidateAccumulator {
+    /// Appends the text of every candidate in `chunk` to the matching slot.
+    pub fn push(&mut self, chunk: &GenerationResponse) {
+        for (position, candidate) in chunk.candidates.iter().enumerate() {
+            let index = candidate_index(candidate, position);
+            if self.texts.len() <= index {
+                self.texts.resize(index + 1, String::new());
+            }
+            self.texts[index].push_str(&candidate_text(candidate));
+        }
+    }
+
+    /// Text accumulated so far, indexed by candidate.
+    pub fn texts(&self) -> &[String] {
+        &self.texts
+    }
+
+    pub fn into_texts(self) -> Vec<String> {
+        self.texts
+    }
+}
+
+/// Turns the first candidate of a response into a `Role::Model` content, ready to be appended
+/// to the conversation history for the next turn. All parts are kept (text, thoughts, function
+/// calls). Returns `None` for empty or blocked responses.
+pub fn response_to_content(response: &GenerationResponse) -> Option<Content> {
+    let candidate = response.candidates.first()?;
+    let parts = candidate.content.parts.clone().filter(|parts| !parts.is_empty())?;
+
+    Some(Content {
+        parts: Some(parts),
+        role: Some(Role::Model),
+    })
+}
+
+/// Function calls requested by the first candidate, in order. Empty if the model answered
+/// with text only.
+pub fn extract_function_calls(response: &GenerationResponse) -> Vec<FunctionCall> {
+    let Some(candidate) = response.candidates.first() else { return Vec::new() };
+    candidate.content.parts.iter()
+        .flatten()
+        .filter_map(|part| match part {
+            Part::FunctionCall { function_call, .. } => Some(function_call.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Builds the turn answering the model's function calls. Append it to the history after
+/// [`response_to_content`] of the response that requested them, then send the next request.
+pub fn function_responses_content(responses: impl IntoIterator<Item = FunctionResponse>) -> Content {
+    let parts = responses.into_iter()
+        .map(|function_response| Part::FunctionResponse { function_response })
+        .collect();
+
+    Content {
+        parts: Some(parts),
+        role: Some(Role::User),
+    }
+}
+
+/// Token usage of a generation, with the counts older responses omit defaulted to 0.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub candidates_tokens: u64,
+    pub total_tokens: u64,
+    /// Prompt tokens served from the context cache (billed at a reduced rate).
+    pub cached_tokens: u64,
+    /// Tokens spent on thinking (billed as output).
+    pub thoughts_tokens: u64,
+}
+
+impl From<&UsageMetadata> for Usage {
+    fn from(usage: &UsageMetadata) -> Self {
+        let count = |n: Option<i32>| n.unwrap_or(0).max(0) as u64;
+        Self {
+            prompt_tokens: count(usage.prompt_token_count),
+            candidates_tokens: count(usage.candidates_token_count),
+            total_tokens: count(usage.total_token_count),
+            cached_tokens: count(usage.cached_content_token_count),
+            thoughts_tokens: count(usage.thoughts_token_count),
+        }
+    }
+}
+
+impl std::ops::Add for Usage {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            candidates_tokens: self.candidates_tokens + other.candidates_tokens,
+            total_tokens: self.total_tokens + other.total_tokens,
+            cached_tokens: self.cached_tokens + other.cached_tokens,
+            thoughts_tokens: self.thoughts_tokens + other.thoughts_tokens,
+        }
+    }
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+/// Result of [`crate::CodeAssistClient::generate_content_detailed`].
+#[derive(Debug, Clone)]
+pub struct DetailedResponse {
+    /// The response as received, including any partial text of a blocked answer.
+    pub response: GenerationResponse,
+    /// Why the first candidate stopped (STOP, MAX_TOKENS, SAFETY, ...), `None` if the server
+    /// didn't say.
+    pub finish_reason: Option<FinishReason>,
+    pub usage: Option<Usage>,
+    /// Set if the prompt or the answer was blocked by the safety filters.
+    pub blocked: Option<Block>,
+}
+
+/// Why a prompt or an answer was blocked, see [`DetailedResponse::blocked`].
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub part: BlockedPart,
+    /// e.g. "SAFETY" or "RECITATION".
+    pub reason: String,
+    pub ratings: Vec<SafetyRating>,
+}
+
+impl DetailedResponse {
+    pub(crate) fn new(response: GenerationResponse) -> Self {
+        let blocked = match check_blocked(&response) {
+            Err(AdapterError::ContentBlocked { part, reason, ratings }) => Some(Block { part, reason, ratings }),
+            _ => None,
+        };
+
+        Self {
+            finish_reason: response.candidates.first().and_then(|c| c.finish_reason.clone()),
+            usage: usage(&response),
+            blocked,
+            response,
+        }
+    }
+
+    /// Whether the answer was cut off by `max_output_tokens`, e.g. to offer a "continue" action.
+    pub fn is_truncated(&self) -> bool {
+        self.finish_reason.as_ref().is_some_and(|reason| enum_name(reason) == "MAX_TOKENS")
+    }
+}
+
+/// Token usage reported in a response, if any.
+pub fn usage(response: &GenerationResponse) -> Option<Usage> {
+    response.usage_metadata.as_ref().map(Usage::from)
+}
+
+/// Tracks the usage of a streamed generation.
+///
+/// The counts in streamed chunks are cumulative, so the latest report is the total
+/// (summing the chunks would count tokens several times).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UsageAccumulator {
+    usage: Option<Usage>,
+}
+
+impl UsageAccumulator {
+    pub fn push(&mut self, chunk: &GenerationResponse) {
+        if let Some(usage) = usage(chunk) {
+            self.usage = Some(usage);
+        }
+    }
+
+    /// Usage of the generation so far, `None` if no chunk reported any.
+    pub fn usage(&self) -> Option<Usage> {
+        self.usage
+    }
+}
+
+/// Usage of a running stream, shared with the stream itself.
+/// See [`crate::CodeAssistClient::generate_content_stream_with_usage`].
+#[derive(Debug, Default, Clone)]
+pub struct StreamUsage {
+    inner: std::sync::Arc<std::sync::Mutex<UsageAccumulator>>,
+}
+
+impl StreamUsage {
+    pub(crate) fn push(&self, chunk: &GenerationResponse) {
+        self.inner.lock().unwrap().push(chunk);
+    }
+
+    /// Usage reported so far; final once the stream has ended.
+    pub fn usage(&self) -> Option<Usage> {
+        self.inner.lock().unwrap().usage()
+    }
+}
+
+/// Feedback about the prompt itself, present when the whole prompt was blocked.
+pub fn prompt_feedback(response: &GenerationResponse) -> Option<PromptFeedback> {
+    response.prompt_feedback.clone()
+}
+
+/// Returns [`AdapterError::ContentBlocked`] if the prompt was blocked.
+pub(crate) fn check_prompt_blocked(response: &GenerationResponse) -> Result<(), AdapterError> {
+    let Some(feedback) = &response.prompt_feedback else { return Ok(()) };
+    let Some(reason) = &feedback.block_reason else { return Ok(()) };
+
+    Err(AdapterError::ContentBlocked {
+        part: BlockedPart::Prompt,
+        reason: enum_name(reason),
+        ratings: feedback.safety_ratings.clone(),
+    })
+}
+
+/// Finish reasons meaning the answer was cut off by a content filter.
+const BLOCKING_FINISH_REASONS: &[&str] = &["SAFETY", "RECITATION", "BLOCKLIST", "PROHIBITED_CONTENT", "SPII", "IMAGE_SAFETY"];
+
+/// Why the first candidate was blocked, with its safety ratings, if it was.
+pub(crate) fn response_block(candidate: &Candidate) -> Option<(String, Vec<gemini_rust::SafetyRating>)> {
+    let reason = enum_name(candidate.finish_reason.as_ref()?);
+    BLOCKING_FINISH_REASONS
+        .contains(&reason.as_str())
+        .then(|| (reason, candidate.safety_ratings.clone().unwrap_or_default()))
+}
+
+/// Returns [`AdapterError::ContentBlocked`] if the prompt or the answer was blocked.
+pub(crate) fn check_blocked(response: &GenerationResponse) -> Result<(), AdapterError> {
+    check_prompt_blocked(response)?;
+    let Some((reason, ratings)) = response.candidates.first().and_then(response_block) else { return Ok(()) };
+
+    Err(AdapterError::ContentBlocked {
+        part: BlockedPart::Response,
+        reason,
+        ratings,
+    })
+}
+
+/// API name of a serde enum (e.g. "SAFETY"), falling back to its Debug form.
+pub(crate) fn enum_name<T: serde::Serialize + std::fmt::Debug>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        _ => format!("{:?}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(json: serde_json::Value) -> GenerationResponse {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn detailed_response_reports_truncation() {
+        let detailed = DetailedResponse::new(response(serde_json::json!({
+            "candidates": [{"content": {"role": "model", "parts": [{"text": "Once upon"}]}, "finishReason": "MAX_TOKENS"}],
+            "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 2, "totalTokenCount": 5}
+        })));
+
+        assert!(detailed.is_truncated());
+        assert!(detailed.blocked.is_none());
+        assert_eq!(detailed.usage.unwrap().total_tokens, 5);
+    }
+
+    #[test]
+    fn detailed_response_keeps_blocked_answer() {
+        let detailed = DetailedResponse::new(response(serde_json::json!({
+            "candidates": [{
+                "content": {"role": "model", "parts": [{"text": "Partial"}]},
+                "finishReason": "SAFETY",
+                "safetyRatings": [{"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "HIGH"}]
+            }]
+        })));
+
+        assert!(!detailed.is_truncated());
+        assert_eq!(detailed.finish_reason.as_ref().map(enum_name).as_deref(), Some("SAFETY"));
+        assert_eq!(detailed.response.text(), "Partial");
+        let block = detailed.blocked.unwrap();
+        assert_eq!(block.part, BlockedPart::Response);
+        assert_eq!(block.reason, "SAFETY");
+        assert_eq!(block.ratings.len(), 1);
+    }
+}
@@ -0,0 +1,124 @@
+//! Retrying transient API failures with exponential backoff.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How `generateContent` requests are retried on transient errors (429, 500, 502, 503, 504),
+/// see [`crate::CodeAssistClient::with_retry_policy`]. Other errors fail right away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt, 0 disables retrying.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for every further one.
+    pub base_delay: Duration,
+    /// Upper bound for a single delay, also applied to the server's `Retry-After`.
+    pub max_delay: Duration,
+    /// Randomize each delay between half and the full value, so clients don't retry in lockstep.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Schedule for polling the onboarding operation: 5 polls after the first request, waiting
+    /// 2, 4, 8, 16 and 16 seconds before them. With jitter, each wait is randomized to between
+    /// half and all of that.
+    pub fn onboarding() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_secs(2),
+            max_delay: Duration::from_secs(16),
+            jitter: true,
+        }
+    }
+
+    pub(crate) fn is_retryable(status: u16) -> bool {
+        matches!(status, 429 | 500 | 502 | 503 | 504)
+    }
+
+    /// Delay before retry number `attempt` (starting at 1), preferring the server's `Retry-After`.
+    pub(crate) fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(delay) = retry_after {
+            return delay.min(self.max_delay);
+        }
+
+        let delay = self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+
+        // Clock nanoseconds are random enough to spread retries apart
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+        delay / 2 + (delay / 2).mul_f64(nanos as f64 / 1e9)
+    }
+}
+
+/// Parses a `Retry-After` header given in seconds (the HTTP-date form is not used by Google APIs).
+pub(crate) fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+            jitter,
+        }
+    }
+
+    #[test]
+    fn delay_doubles_up_to_max() {
+        let delays: Vec<_> = (1..=5).map(|attempt| policy(false).delay(attempt, None)).collect();
+        assert_eq!(delays, [1, 2, 4, 5, 5].map(Duration::from_secs));
+    }
+
+    #[test]
+    fn delay_prefers_retry_after_capped_at_max() {
+        assert_eq!(policy(false).delay(1, Some(Duration::from_secs(3))), Duration::from_secs(3));
+        assert_eq!(policy(true).delay(1, Some(Duration::from_secs(60))), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn jittered_delay_stays_in_range() {
+        for attempt in 1..=5 {
+            let full = policy(false).delay(attempt, None);
+            let delay = policy(true).delay(attempt, None);
+            assert!(delay >= full / 2 && delay <= full, "{:?} not within {:?}", delay, full);
+        }
+    }
+
+    #[test]
+    fn onboarding_schedule() {
+        let policy = RetryPolicy { jitter: false, ..RetryPolicy::onboarding() };
+        let delays: Vec<_> = (1..=policy.max_retries).map(|attempt| policy.delay(attempt, None)).collect();
+        assert_eq!(delays, [2, 4, 8, 16, 16].map(Duration::from_secs));
+    }
+
+    #[test]
+    fn parses_retry_after_seconds() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(reqwest::header::RETRY_AFTER, " 7 ".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(7)));
+
+        headers.insert(reqwest::header::RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(retry_after(&headers), None);
+    }
+}
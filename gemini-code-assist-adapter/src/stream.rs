@@ -0,0 +1,677 @@
+//! Combinators for the response streams returned by [`crate::CodeAssistClient`].
+
+use std::io::Write;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use futures::{Stream, StreamExt};
+use std::collections::VecDeque;
+use gemini_rust::{FinishReason, FunctionCall, GenerationResponse, Part, SafetyRating};
+use serde::de::DeserializeOwned;
+use crate::error::AdapterError;
+use crate::response::{self, Usage, UsageAccumulator};
+
+/// How often [`paced_text`] releases text.
+const PACE_TICK: Duration = Duration::from_millis(30);
+/// Maximum amount of text (in seconds at the configured rate) [`paced_text`] may hold back.
+const PACE_MAX_LAG_SECS: f64 = 1.0;
+
+/// Wire format used for `streamGenerateContent`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StreamTransport {
+    /// Server-sent events (`alt=sse`).
+    #[default]
+    Sse,
+    /// A single JSON array whose elements arrive over time. More robust with proxies that cut
+    /// idle SSE connections.
+    JsonArray,
+}
+
+/// Splits a streamed top-level JSON array (`[{..},{..}]`) into its elements.
+#[derive(Debug, Default)]
+pub(crate) struct JsonArraySplitter {
+    buf: Vec<u8>,
+    /// How far `buf` has been scanned.
+    scanned: usize,
+    /// Start of the element being read, if any.
+    start: Option<usize>,
+    depth: u32,
+    in_string: bool,
+    escaped: bool,
+    opened: bool,
+    closed: bool,
+}
+
+impl JsonArraySplitter {
+    /// Feeds more bytes and returns the elements completed by them, as raw JSON.
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Vec<Result<String, AdapterError>> {
+        self.buf.extend_from_slice(bytes);
+        let mut elements = Vec::new();
+
+        while self.scanned < self.buf.len() && !self.closed {
+            let i = self.scanned;
+            let b = self.buf[i];
+            self.scanned += 1;
+
+            let Some(start) = self.start else {
+                match b {
+                    _ if b.is_ascii_whitespace() => {}
+                    b'[' if !self.opened => self.opened = true,
+                    b',' if self.opened => {}
+                    b']' if self.opened => self.closed = true,
+                    _ if self.opened => {
+                        self.start = Some(i);
+                        self.scanned = i; // re-read the first byte as part of the element
+                    }
+                    _ => {
+                        self.closed = true;
+                        elements.push(Err(AdapterError::StreamError(
+                            format!("Expected a JSON array, got {:?}", b as char).into(),
+                        )));
+                    }
+                }
+                continue;
+            };
+
+            if self.in_string {
+                match b {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+
+            match b {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+
+            if self.depth == 0 && matches!(b, b'}' | b']') {
+                let element = String::from_utf8_lossy(&self.buf[start..=i]).into_owned();
+                elements.push(Ok(element));
+                self.buf.drain(..=i);
+                self.scanned = 0;
+                self.start = None;
+            }
+        }
+
+        elements
+    }
+
+    /// Checks the array was complete once the body has ended. A connection cut mid-stream
+    /// (e.g. by a proxy) leaves it unclosed or with an element half read.
+    pub(crate) fn finish(&self) -> Result<(), AdapterError> {
+        if self.closed && self.start.is_none() {
+            return Ok(());
+        }
+        Err(AdapterError::StreamError("the response ended before the JSON array was complete".into()))
+    }
+}
+
+/// What to do when writing to the tee target fails.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WriteErrorMode {
+    /// Yield the write error as a stream item.
+    #[default]
+    Fail,
+    /// Log the error and keep streaming.
+    Ignore,
+}
+
+/// Passes every chunk through unchanged while also writing its text to `writer`.
+pub fn tee_to_writer<S, W>(
+    stream: S,
+    mut writer: W,
+    mode: WriteErrorMode,
+) -> impl Stream<Item = Result<GenerationResponse, AdapterError>>
+where
+    S: Stream<Item = Result<GenerationResponse, AdapterError>>,
+    W: Write,
+{
+    stream.map(move |item| {
+        let Ok(response) = &item else { return item };
+
+        let written = writer
+            .write_all(response.text().as_bytes())
+            .and_then(|_| writer.flush());
+
+        match (written, mode) {
+            (Err(e), WriteErrorMode::Fail) => Err(AdapterError::Io(e)),
+            (Err(e), WriteErrorMode::Ignore) => {
+                log::debug!("Failed to tee stream output: {}", e);
+                item
+            }
+            (Ok(()), _) => item,
+        }
+    })
+}
+
+/// Drains a stream into a single `GenerationResponse`, as if it had been requested without
+/// streaming: consecutive text parts are concatenated per candidate, other parts (function
+/// calls, ...) are kept in order, and the last reported finish reason and usage win.
+/// The first error ends the collection and is returned, as does a stream without any chunk.
+pub async fn collect_stream<S>(stream: S) -> Result<GenerationResponse, AdapterError>
+where
+    S: Stream<Item = Result<GenerationResponse, AdapterError>>,
+{
+    let mut stream = std::pin::pin!(stream);
+    let mut collected: Option<GenerationResponse> = None;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        match &mut collected {
+            Some(response) => merge_chunk(response, chunk),
+            None => collected = Some(chunk),
+        }
+    }
+    collected.ok_or_else(|| AdapterError::StreamError("the stream ended without any response".into()))
+}
+
+fn merge_chunk(response: &mut GenerationResponse, chunk: GenerationResponse) {
+    for (position, candidate) in chunk.candidates.into_iter().enumerate() {
+        let index = candidate.index.unwrap_or(position as i32);
+        let existing = response.candidates.iter_mut()
+            .enumerate()
+            .find(|(i, c)| c.index.unwrap_or(*i as i32) == index)
+            .map(|(_, c)| c);
+        let Some(existing) = existing else {
+            response.candidates.push(candidate);
+            continue;
+        };
+
+        let parts = existing.content.parts.get_or_insert_with(Vec::new);
+        for part in candidate.content.parts.into_iter().flatten() {
+            match (parts.last_mut(), part) {
+                (
+                    Some(Part::Text { text, thought, thought_signature }),
+                    Part::Text { text: more, thought: more_thought, thought_signature: signature },
+                ) if *thought == more_thought => {
+                    text.push_str(&more);
+                    if signature.is_some() {
+                        *thought_signature = signature;
+                    }
+                }
+                (
+                    Some(Part::FunctionCall { function_call, .. }),
+                    Part::FunctionCall { function_call: more, .. },
+                ) if is_continuation(&more) => merge_function_call(function_call, more),
+                (_, part) => parts.push(part),
+            }
+        }
+        if candidate.finish_reason.is_some() {
+            existing.finish_reason = candidate.finish_reason;
+        }
+        if candidate.safety_ratings.is_some() {
+            existing.safety_ratings = candidate.safety_ratings;
+        }
+    }
+
+    if chunk.usage_metadata.is_some() {
+        response.usage_metadata = chunk.usage_metadata;
+    }
+    if chunk.prompt_feedback.is_some() {
+        response.prompt_feedback = chunk.prompt_feedback;
+    }
+    if response.model_version.is_none() {
+        response.model_version = chunk.model_version;
+    }
+}
+
+/// Collects the text of a JSON-mode stream and parses it as `T` once the stream has finished.
+///
+/// The chunks are fragments of a single JSON document, so the resulting stream yields exactly
+/// one item: the parsed value, or the first error (transport or parse) that occurred.
+pub fn json_stream<S, T>(stream: S) -> impl Stream<Item = Result<T, AdapterError>>
+where
+    S: Stream<Item = Result<GenerationResponse, AdapterError>>,
+    T: DeserializeOwned,
+{
+    futures::stream::once(async move {
+        let mut stream = std::pin::pin!(stream);
+        let mut json = String::new();
+        while let Some(chunk) = stream.next().await {
+            json.push_str(&chunk?.text());
+        }
+        Ok(serde_json::from_str(&json)?)
+    })
+}
+
+/// Ends the stream with a [`AdapterError::StreamError`] once no item arrived for `timeout`,
+/// dropping (and thereby closing) the source.
+pub(crate) fn idle_timeout<S, T>(stream: S, timeout: Duration) -> impl Stream<Item = Result<T, AdapterError>>
+where
+    S: Stream<Item = Result<T, AdapterError>> + Unpin,
+{
+    futures::stream::unfold(Some(stream), move |stream| async move {
+        let mut stream = stream?;
+        match tokio::time::timeout(timeout, stream.next()).await {
+            Ok(Some(item)) => Some((item, Some(stream))),
+            Ok(None) => None,
+            Err(_) => Some((Err(AdapterError::StreamError("timeout".into())), None)),
+        }
+    })
+}
+
+/// Calls `f` once the stream has ended. Nothing is called if the stream is dropped before.
+pub(crate) fn on_end<S>(stream: S, f: impl FnOnce() + Send) -> impl Stream<Item = S::Item>
+where
+    S: Stream,
+{
+    let mut f = Some(f);
+    stream.chain(futures::stream::poll_fn(move |_| {
+        if let Some(f) = f.take() {
+            f();
+        }
+        std::task::Poll::Ready(None)
+    }))
+}
+
+/// Re-emits the text of a stream at a steady "typewriter" pace of about `chars_per_sec`.
+///
+/// Bursts are smoothed out, but the output never lags more than about a second behind the
+/// generation: beyond that the pace speeds up. Once the source stream ends, the remaining
+/// text is released at once. Errors are passed through after the text received before them.
+pub fn paced_text<S>(stream: S, chars_per_sec: f64) -> impl Stream<Item = Result<String, AdapterError>>
+where
+    S: Stream<Item = Result<GenerationResponse, AdapterError>> + Send + 'static,
+{
+    struct State<S> {
+        source: Option<Pin<Box<S>>>,
+        buffer: String,
+        error: Option<AdapterError>,
+        last_release: Instant,
+        /// Fractional characters owed from previous releases.
+        credit: f64,
+    }
+
+    let state = State {
+        source: Some(Box::pin(stream)),
+        buffer: String::new(),
+        error: None,
+        last_release: Instant::now(),
+        credit: 0.0,
+    };
+    let rate = chars_per_sec.max(1.0);
+
+    futures::stream::unfold(state, move |mut state| async move {
+        loop {
+            let Some(source) = state.source.as_mut() else {
+                // Source is done: drain everything, then the error (if any)
+                if !state.buffer.is_empty() {
+                    return Some((Ok(std::mem::take(&mut state.buffer)), state));
+                }
+                return state.error.take().map(|e| (Err(e), state));
+            };
+
+            if state.buffer.is_empty() {
+                let item = source.next().await;
+                state.last_release = Instant::now();
+                state.credit = 0.0;
+                push_item(&mut state.source, &mut state.buffer, &mut state.error, item);
+                continue;
+            }
+
+            tokio::select! {
+                item = source.next() => {
+                    push_item(&mut state.source, &mut state.buffer, &mut state.error, item);
+                }
+                // Deadline is fixed per release, so a fast source can't starve the output
+                _ = tokio::time::sleep_until((state.last_release + PACE_TICK).into()) => {
+                    let now = Instant::now();
+                    let due = state.credit + (now - state.last_release).as_secs_f64() * rate;
+                    let backlog = state.buffer.chars().count() as f64 - rate * PACE_MAX_LAG_SECS;
+                    let count = due.max(backlog).max(1.0).floor();
+                    state.credit = (due - count).max(0.0);
+                    state.last_release = now;
+                    let count = count as usize;
+
+                    let split = state.buffer
+                        .char_indices()
+                        .nth(count)
+                        .map_or(state.buffer.len(), |(i, _)| i);
+                    let released: String = state.buffer.drain(..split).collect();
+                    return Some((Ok(released), state));
+                }
+            }
+        }
+    })
+}
+
+/// Handles the next item of the source stream of [`paced_text`].
+fn push_item<S>(
+    source: &mut Option<S>,
+    buffer: &mut String,
+    error: &mut Option<AdapterError>,
+    item: Option<Result<GenerationResponse, AdapterError>>,
+) {
+    match item {
+        Some(Ok(response)) => buffer.push_str(&response.text()),
+        Some(Err(e)) => {
+            *error = Some(e);
+            *source = None;
+        }
+        None => *source = None,
+    }
+}
+
+/// A function call whose arguments are split over several chunks arrives as a named call
+/// followed by nameless parts carrying the rest of the arguments.
+fn is_continuation(call: &FunctionCall) -> bool {
+    call.name.is_empty()
+}
+
+/// Adds the arguments of a continuation part to the call it belongs to.
+fn merge_function_call(call: &mut FunctionCall, more: FunctionCall) {
+    match (&mut call.args, more.args) {
+        (serde_json::Value::Object(args), serde_json::Value::Object(more)) => args.extend(more),
+        (args, more) if args.is_null() => *args = more,
+        (_, more) => log::warn!("Dropping function call arguments that can't be merged: {}", more),
+    }
+}
+
+/// Item of [`crate::CodeAssistClient::generate_content_stream_resumable`].
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    Chunk(GenerationResponse),
+    /// The connection dropped and the generation was resumed with a new request.
+    /// `attempt` starts at 1.
+    Reconnected { attempt: u32 },
+}
+
+/// Event of [`function_call_events`].
+#[derive(Debug, Clone)]
+pub enum FunctionCallEvent {
+    /// Text (non-thought) of the first candidate.
+    Text(String),
+    /// A function call started. `index` counts calls within the stream.
+    FunctionCallStart { index: usize, name: String },
+    /// A fragment of the call's JSON arguments, one per chunk the arguments arrived in.
+    ArgsDelta { index: usize, delta: String },
+    /// The call is complete, with all argument fragments merged.
+    FunctionCallEnd { index: usize, call: FunctionCall },
+}
+
+/// Splits a stream into text and function call events, in the order they arrive.
+///
+/// Lets an agent start preparing tool execution as soon as a call shows up. A call whose
+/// arguments are split over several chunks yields one [`FunctionCallEvent::ArgsDelta`] per
+/// chunk; it ends when the next call or text arrives, or the stream ends. Errors are passed
+/// through without ending the stream.
+pub fn function_call_events<S>(stream: S) -> impl Stream<Item = Result<FunctionCallEvent, AdapterError>>
+where
+    S: Stream<Item = Result<GenerationResponse, AdapterError>>,
+{
+    struct State<S> {
+        source: Option<Pin<Box<S>>>,
+        pending: VecDeque<Result<FunctionCallEvent, AdapterError>>,
+        /// Call that may still receive arguments from the next parts.
+        call: Option<(usize, FunctionCall)>,
+        next_index: usize,
+    }
+
+    impl<S> State<S> {
+        fn end_call(&mut self) {
+            if let Some((index, call)) = self.call.take() {
+                self.pending.push_back(Ok(FunctionCallEvent::FunctionCallEnd { index, call }));
+            }
+        }
+
+        fn push_call(&mut self, function_call: FunctionCall) {
+            match &mut self.call {
+                Some((index, call)) if is_continuation(&function_call) => {
+                    self.pending.push_back(Ok(FunctionCallEvent::ArgsDelta {
+                        index: *index,
+                        delta: function_call.args.to_string(),
+                    }));
+                    merge_function_call(call, function_call);
+                }
+                _ => {
+                    self.end_call();
+                    let index = self.next_index;
+                    self.next_index += 1;
+                    self.pending.push_back(Ok(FunctionCallEvent::FunctionCallStart {
+                        index,
+                        name: function_call.name.clone(),
+                    }));
+                    self.pending.push_back(Ok(FunctionCallEvent::ArgsDelta {
+                        index,
+                        delta: function_call.args.to_string(),
+                    }));
+                    self.call = Some((index, function_call));
+                }
+            }
+        }
+    }
+
+    let state = State {
+        source: Some(Box::pin(stream)),
+        pending: VecDeque::new(),
+        call: None,
+        next_index: 0,
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((event, state));
+            }
+
+            match state.source.as_mut()?.next().await {
+                Some(Ok(response)) => {
+                    let parts = response.candidates
+                        .into_iter()
+                        .next()
+                        .and_then(|c| c.content.parts)
+                        .unwrap_or_default();
+
+                    for part in parts {
+                        match part {
+                            Part::Text { text, thought, .. } if !thought.unwrap_or(false) => {
+                                state.end_call();
+                                state.pending.push_back(Ok(FunctionCallEvent::Text(text)));
+                            }
+                            Part::FunctionCall { function_call, .. } => state.push_call(function_call),
+                            _ => {}
+                        }
+                    }
+                }
+                Some(Err(e)) => state.pending.push_back(Err(e)),
+                None => {
+                    state.source = None;
+                    state.end_call();
+                }
+            }
+        }
+    })
+}
+
+/// Event of [`generation_events`].
+#[derive(Debug, Clone)]
+pub enum GenerationEvent {
+    /// Answer text of the first candidate.
+    Text(String),
+    /// Reasoning text of the first candidate (a part flagged as `thought`).
+    Thought(String),
+    FunctionCall(FunctionCall),
+    /// The answer was cut off by a content filter, e.g. `reason` "SAFETY". Followed by `Done`.
+    Blocked {
+        reason: String,
+        ratings: Vec<SafetyRating>,
+    },
+    /// The stream has finished. Not emitted if it failed.
+    Done {
+        finish_reason: Option<FinishReason>,
+        usage: Option<Usage>,
+    },
+}
+
+/// Turns a stream of response chunks into typed events, separating thoughts from the answer
+/// and ending with a single [`GenerationEvent::Done`] that carries the finish reason and usage.
+/// A blocked answer is reported as [`GenerationEvent::Blocked`] with the tripped safety ratings.
+/// Function calls are emitted once complete, with arguments split over several chunks merged.
+pub fn generation_events<S>(stream: S) -> impl Stream<Item = Result<GenerationEvent, AdapterError>>
+where
+    S: Stream<Item = Result<GenerationResponse, AdapterError>> + Unpin,
+{
+    struct State<S> {
+        source: Option<S>,
+        pending: VecDeque<GenerationEvent>,
+        /// Call that may still receive arguments from the next parts.
+        call: Option<FunctionCall>,
+        finish_reason: Option<FinishReason>,
+        usage: UsageAccumulator,
+    }
+
+    impl<S> State<S> {
+        fn flush_call(&mut self) {
+            if let Some(call) = self.call.take() {
+                self.pending.push_back(GenerationEvent::FunctionCall(call));
+            }
+        }
+
+        fn push(&mut self, event: GenerationEvent) {
+            self.flush_call();
+            self.pending.push_back(event);
+        }
+    }
+
+    let state = State {
+        source: Some(stream),
+        pending: VecDeque::new(),
+        call: None,
+        finish_reason: None,
+        usage: UsageAccumulator::default(),
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((Ok(event), state));
+            }
+
+            match state.source.as_mut()?.next().await {
+                Some(Ok(response)) => {
+                    state.usage.push(&response);
+                    let Some(candidate) = response.candidates.into_iter().next() else { continue };
+                    let blocked = response::response_block(&candidate);
+                    if candidate.finish_reason.is_some() {
+                        state.finish_reason = candidate.finish_reason;
+                    }
+                    for part in candidate.content.parts.into_iter().flatten() {
+                        match part {
+                            Part::Text { text, thought: Some(true), .. } => state.push(GenerationEvent::Thought(text)),
+                            Part::Text { text, .. } => state.push(GenerationEvent::Text(text)),
+                            Part::FunctionCall { function_call, .. } => match &mut state.call {
+                                Some(call) if is_continuation(&function_call) => merge_function_call(call, function_call),
+                                _ => {
+                                    state.flush_call();
+                                    state.call = Some(function_call);
+                                }
+                            },
+                            _ => {}
+                        }
+                    }
+                    if let Some((reason, ratings)) = blocked {
+                        state.push(GenerationEvent::Blocked { reason, ratings });
+                    }
+                }
+                Some(Err(e)) => {
+                    state.source = None;
+                    return Some((Err(e), state));
+                }
+                None => {
+                    state.source = None;
+                    state.flush_call();
+                    state.pending.push_back(GenerationEvent::Done {
+                        finish_reason: state.finish_reason.take(),
+                        usage: state.usage.usage(),
+                    });
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_array_splitter_splits_across_chunks() {
+        let mut splitter = JsonArraySplitter::default();
+        let mut elements = splitter.push(br#"[{"a": "x]"},"#);
+        elements.extend(splitter.push(br#" {"b": [1, {"c": 2}]"#));
+        elements.extend(splitter.push(b"}]"));
+
+        let elements: Vec<_> = elements.into_iter().map(Result::unwrap).collect();
+        assert_eq!(elements, [r#"{"a": "x]"}"#, r#"{"b": [1, {"c": 2}]}"#]);
+        assert!(splitter.finish().is_ok());
+    }
+
+    #[test]
+    fn json_array_splitter_detects_truncation() {
+        let mut unclosed = JsonArraySplitter::default();
+        assert_eq!(unclosed.push(br#"[{"a": 1},"#).len(), 1);
+        assert!(unclosed.finish().is_err());
+
+        let mut mid_element = JsonArraySplitter::default();
+        assert_eq!(mid_element.push(br#"[{"a": 1}, {"b": "#).len(), 1);
+        assert!(mid_element.finish().is_err());
+
+        assert!(JsonArraySplitter::default().finish().is_err());
+    }
+
+    fn chunk(part: serde_json::Value) -> Result<GenerationResponse, AdapterError> {
+        let response = serde_json::json!({"candidates": [{"content": {"role": "model", "parts": [part]}}]});
+        Ok(serde_json::from_value(response).unwrap())
+    }
+
+    fn call(name: &str, args: serde_json::Value) -> Result<GenerationResponse, AdapterError> {
+        chunk(serde_json::json!({"functionCall": {"name": name, "args": args}}))
+    }
+
+    #[tokio::test]
+    async fn function_call_events_merge_split_arguments() {
+        let chunks = vec![
+            call("read_file", serde_json::json!({"path": "src/lib.rs"})),
+            call("", serde_json::json!({"lines": 10})),
+            chunk(serde_json::json!({"text": "Reading"})),
+        ];
+        let events: Vec<_> = function_call_events(futures::stream::iter(chunks))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        assert!(matches!(&events[0], FunctionCallEvent::FunctionCallStart { index: 0, name } if name == "read_file"));
+        assert!(matches!(&events[1], FunctionCallEvent::ArgsDelta { index: 0, .. }));
+        assert!(matches!(&events[2], FunctionCallEvent::ArgsDelta { index: 0, delta } if delta == r#"{"lines":10}"#));
+        match &events[3] {
+            FunctionCallEvent::FunctionCallEnd { index: 0, call } => {
+                assert_eq!(call.args, serde_json::json!({"path": "src/lib.rs", "lines": 10}));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(matches!(&events[4], FunctionCallEvent::Text(text) if text == "Reading"));
+        assert_eq!(events.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn function_call_events_end_call_at_end_of_stream() {
+        let chunks = vec![call("a", serde_json::json!({})), call("b", serde_json::json!({}))];
+        let events: Vec<_> = function_call_events(futures::stream::iter(chunks))
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        let ends: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                FunctionCallEvent::FunctionCallEnd { index, call } => Some((*index, call.name.as_str())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(ends, [(0, "a"), (1, "b")]);
+    }
+}
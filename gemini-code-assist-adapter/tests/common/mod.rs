@@ -0,0 +1,47 @@
+//! Minimal HTTP server standing in for the Code Assist backend.
+
+#![allow(dead_code)] // each test binary uses a different part
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A canned response: status line (e.g. "200 OK"), content type and body.
+pub struct Reply {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Reply {
+    pub fn json(status: &'static str, body: impl Into<String>) -> Self {
+        Self { status, content_type: "application/json", body: body.into() }
+    }
+
+    /// An SSE body with one `data:` event per entry.
+    pub fn sse(events: &[&str]) -> Self {
+        let body = events.iter().map(|event| format!("data: {}\r\n\r\n", event)).collect();
+        Self { status: "200 OK", content_type: "text/event-stream", body }
+    }
+}
+
+/// Serves the replies, one connection each, in order, and returns the base URL to use.
+pub async fn serve(replies: Vec<Reply>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        for reply in replies {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 64 * 1024];
+            let _ = socket.read(&mut request).await;
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nRetry-After: 0\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                reply.status,
+                reply.content_type,
+                reply.body.len(),
+                reply.body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    format!("http://{}/v1internal", addr)
+}
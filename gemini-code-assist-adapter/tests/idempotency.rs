@@ -0,0 +1,23 @@
+use std::time::Duration;
+use gemini_code_assist_adapter::{CodeAssistClient, RequestBuilder};
+
+mod common;
+use common::{serve, Reply};
+
+const ANSWER: &str = r#"{"response": {"candidates": [{"content": {"role": "model", "parts": [{"text": "hi"}]}}]}}"#;
+
+#[tokio::test]
+async fn concurrent_calls_with_the_same_key_send_one_request() {
+    // A single reply: a second request would never be answered
+    let base_url = serve(vec![Reply::json("200 OK", ANSWER)]).await;
+    let client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(base_url)
+        .with_idempotency_key("send-1");
+    let clone = client.clone();
+    let request = RequestBuilder::new().user("hello").build();
+
+    let both = async { tokio::join!(client.generate_content(&request), clone.generate_content(&request)) };
+    let (first, second) = tokio::time::timeout(Duration::from_secs(5), both).await.unwrap();
+    assert_eq!(first.unwrap().text(), "hi");
+    assert_eq!(second.unwrap().text(), "hi");
+}
@@ -0,0 +1,38 @@
+use futures::StreamExt;
+use gemini_code_assist_adapter::{CodeAssistClient, RequestBuilder};
+
+mod common;
+use common::{serve, Reply};
+
+const ANSWER: &str = r#"{"response": {"candidates": [{"content": {"role": "model", "parts": [{"text": "Hello"}]}}]}}"#;
+
+#[tokio::test]
+async fn records_generations_and_streams_separately() {
+    let base_url = serve(vec![
+        Reply::json("200 OK", ANSWER),
+        Reply::sse(&[ANSWER, "[DONE]"]),
+        Reply::sse(&[ANSWER, "[DONE]"]),
+    ]).await;
+    let client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(base_url)
+        .with_latency_tracking(true);
+    let request = RequestBuilder::new().user("hello").build();
+
+    client.generate_content(&request).await.unwrap();
+    let finished = client.generate_content_stream(&request).await.unwrap();
+    assert_eq!(finished.count().await, 1);
+    // Dropped before the end: only the time to first byte is known
+    drop(client.generate_content_stream(&request).await.unwrap());
+
+    let stats = client.latency_stats().unwrap();
+    assert_eq!(stats.generate_first_byte.count(), 1);
+    assert_eq!(stats.generate_total.count(), 1);
+    assert!(stats.generate_total.p50() >= stats.generate_first_byte.p50());
+    assert_eq!(stats.stream_first_byte.count(), 2);
+    assert_eq!(stats.stream_total.count(), 1);
+    assert!(stats.stream_total.p50() >= stats.stream_first_byte.p50());
+
+    stats.reset();
+    assert_eq!(stats.generate_total.count(), 0);
+    assert!(stats.stream_total.p99().is_none());
+}
@@ -0,0 +1,31 @@
+use gemini_code_assist_adapter::models::KNOWN_MODELS;
+use gemini_code_assist_adapter::CodeAssistClient;
+
+mod common;
+use common::{serve, Reply};
+
+const TOKENS: &str = r#"{"totalTokens": 1}"#;
+const NOT_FOUND: &str = r#"{"error": {"code": 404, "message": "Requested entity was not found.", "status": "NOT_FOUND"}}"#;
+
+#[tokio::test]
+async fn lists_known_models_the_backend_accepts() {
+    // One model is rejected; which one depends on the order the probes arrive in
+    let mut replies: Vec<Reply> = (1..KNOWN_MODELS.len()).map(|_| Reply::json("200 OK", TOKENS)).collect();
+    replies.insert(2, Reply::json("404 Not Found", NOT_FOUND));
+    let client = CodeAssistClient::new("token".into(), "project".into()).with_base_url(serve(replies).await);
+
+    let models = client.list_models_full().await.unwrap();
+    assert_eq!(models.len(), KNOWN_MODELS.len() - 1);
+    assert!(models.iter().all(|m| m.supports_generation() && KNOWN_MODELS.contains(m)));
+}
+
+#[tokio::test]
+async fn channel_skips_models_the_backend_rejects() {
+    // The newest flash model (a preview) is gone, the next one answers
+    let base_url = serve(vec![Reply::json("404 Not Found", NOT_FOUND), Reply::json("200 OK", TOKENS)]).await;
+    let mut client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(base_url)
+        .with_model_channel("flash-latest");
+
+    assert_eq!(client.resolve_model_channel().await, "gemini-2.5-flash");
+}
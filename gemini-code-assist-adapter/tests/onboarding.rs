@@ -0,0 +1,63 @@
+use std::time::Duration;
+use gemini_code_assist_adapter::error::AdapterError;
+use gemini_code_assist_adapter::{CancellationToken, CodeAssistClient, RetryPolicy};
+use tokio::net::TcpListener;
+
+mod common;
+use common::{serve, Reply};
+
+const RUNNING: &str = r#"{"name": "operations/onboard-1", "done": false}"#;
+
+#[tokio::test]
+async fn cancel_aborts_request_in_flight() {
+    // Accepts the connection but never answers
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (_socket, _) = listener.accept().await.unwrap();
+        std::future::pending::<()>().await;
+    });
+
+    let mut client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(format!("http://{}/v1internal", addr));
+    let cancel = CancellationToken::new();
+    let trigger = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        trigger.cancel();
+    });
+
+    let result = tokio::time::timeout(Duration::from_secs(5), client.onboard_user_with_progress(|_, _| {}, cancel)).await;
+    assert!(matches!(result, Ok(Err(AdapterError::Cancelled))));
+}
+
+#[tokio::test]
+async fn cancel_interrupts_wait_between_polls() {
+    let base_url = serve(vec![Reply::json("200 OK", RUNNING)]).await;
+    let mut client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(base_url)
+        .with_onboarding_policy(RetryPolicy {
+            max_retries: 1,
+            base_delay: Duration::from_secs(60),
+            max_delay: Duration::from_secs(60),
+            ..RetryPolicy::onboarding()
+        });
+    let cancel = CancellationToken::new();
+
+    let trigger = cancel.clone();
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        client.onboard_user_with_progress(move |_, _| trigger.cancel(), cancel),
+    ).await;
+    assert!(matches!(result, Ok(Err(AdapterError::Cancelled))));
+}
+
+#[tokio::test]
+async fn error_status_is_an_api_error() {
+    let denied = r#"{"error": {"code": 403, "message": "The caller does not have permission", "status": "PERMISSION_DENIED"}}"#;
+    let base_url = serve(vec![Reply::json("403 Forbidden", denied)]).await;
+    let mut client = CodeAssistClient::new("token".into(), "project".into()).with_base_url(base_url);
+
+    let result = client.onboard_user_blocking().await;
+    assert!(matches!(result, Err(AdapterError::ApiError { code: 403, .. })), "{:?}", result);
+}
@@ -0,0 +1,48 @@
+use gemini_code_assist_adapter::{CodeAssistClient, RequestBuilder, RetryPolicy};
+use std::time::Duration;
+
+mod common;
+use common::{serve, Reply};
+
+const UNAVAILABLE: &str = r#"{"error": {"code": 503, "message": "The service is currently unavailable.", "status": "UNAVAILABLE"}}"#;
+const ANSWER: &str = r#"{"response": {"candidates": [{"content": {"role": "model", "parts": [{"text": "hi"}]}}]}}"#;
+
+fn policy(max_retries: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_retries,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(50),
+        jitter: false,
+    }
+}
+
+#[tokio::test]
+async fn retries_transient_errors_until_success() {
+    let base_url = serve(vec![
+        Reply::json("503 Service Unavailable", UNAVAILABLE),
+        Reply::json("503 Service Unavailable", UNAVAILABLE),
+        Reply::json("200 OK", ANSWER),
+    ])
+    .await;
+    let client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(base_url)
+        .with_retry_policy(policy(2));
+
+    let response = client.generate_content(&RequestBuilder::new().user("hello").build()).await.unwrap();
+    assert_eq!(response.text(), "hi");
+}
+
+#[tokio::test]
+async fn gives_up_after_max_retries() {
+    let base_url = serve(vec![
+        Reply::json("503 Service Unavailable", UNAVAILABLE),
+        Reply::json("503 Service Unavailable", UNAVAILABLE),
+    ])
+    .await;
+    let client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(base_url)
+        .with_retry_policy(policy(1));
+
+    let error = client.generate_content(&RequestBuilder::new().user("hello").build()).await.unwrap_err();
+    assert_eq!(error.status_code(), Some(503));
+}
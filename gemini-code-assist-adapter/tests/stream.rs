@@ -0,0 +1,80 @@
+use futures::StreamExt;
+use gemini_code_assist_adapter::{CodeAssistClient, RequestBuilder};
+
+mod common;
+use common::{serve, Reply};
+
+fn chunk(text: &str) -> String {
+    format!(r#"{{"response": {{"candidates": [{{"content": {{"role": "model", "parts": [{{"text": "{}"}}]}}}}]}}}}"#, text)
+}
+
+async fn stream_texts(reply: Reply) -> Vec<String> {
+    let client = CodeAssistClient::new("token".into(), "project".into()).with_base_url(serve(vec![reply]).await);
+    let stream = client.generate_content_stream(&RequestBuilder::new().user("hello").build()).await.unwrap();
+    stream.map(|chunk| chunk.unwrap().text()).collect().await
+}
+
+#[tokio::test]
+async fn stops_at_done_sentinel() {
+    let texts = stream_texts(Reply::sse(&[&chunk("Hel"), &chunk("lo"), "[DONE]", &chunk("ignored")])).await;
+    assert_eq!(texts, ["Hel", "lo"]);
+}
+
+#[tokio::test]
+async fn ends_at_eof_without_sentinel() {
+    let texts = stream_texts(Reply::sse(&[&chunk("Hel"), &chunk("lo")])).await;
+    assert_eq!(texts, ["Hel", "lo"]);
+}
+
+#[tokio::test]
+async fn accepts_sentinel_with_surrounding_whitespace() {
+    let texts = stream_texts(Reply::sse(&[&chunk("Hello"), "  [DONE]  "])).await;
+    assert_eq!(texts, ["Hello"]);
+}
+
+#[tokio::test]
+async fn skips_metadata_only_frames() {
+    let texts = stream_texts(Reply::sse(&[r#"{"traceId": "abc123"}"#, &chunk("Hello"), r#"{"metadata": {}}"#])).await;
+    assert_eq!(texts, ["Hello"]);
+}
+
+#[tokio::test]
+async fn usage_from_trailing_usage_only_chunk() {
+    // Recorded from streamGenerateContent: the last frame only carries the usage
+    let reply = Reply::sse(&[
+        r#"{"response": {"candidates": [{"content": {"role": "model", "parts": [{"text": "Hello"}]}}], "usageMetadata": {"promptTokenCount": 4, "totalTokenCount": 4}, "modelVersion": "gemini-2.5-flash"}, "traceId": "3f2a"}"#,
+        r#"{"response": {"candidates": [{"content": {"role": "model", "parts": [{"text": " world"}]}, "finishReason": "STOP"}], "modelVersion": "gemini-2.5-flash"}, "traceId": "3f2a"}"#,
+        r#"{"response": {"candidates": [], "usageMetadata": {"promptTokenCount": 4, "candidatesTokenCount": 2, "totalTokenCount": 10, "thoughtsTokenCount": 4}, "modelVersion": "gemini-2.5-flash"}, "traceId": "3f2a"}"#,
+    ]);
+    let client = CodeAssistClient::new("token".into(), "project".into()).with_base_url(serve(vec![reply]).await);
+
+    let (stream, usage) = client
+        .generate_content_stream_with_usage(&RequestBuilder::new().user("hello").build())
+        .await
+        .unwrap();
+    assert_eq!(usage.usage().map(|u| u.total_tokens), None);
+
+    let texts: Vec<String> = stream.map(|chunk| chunk.unwrap().text()).collect().await;
+    assert_eq!(texts, ["Hello", " world"]);
+
+    let usage = usage.usage().unwrap();
+    assert_eq!(usage.prompt_tokens, 4);
+    assert_eq!(usage.candidates_tokens, 2);
+    assert_eq!(usage.thoughts_tokens, 4);
+    assert_eq!(usage.total_tokens, 10);
+}
+
+#[tokio::test]
+async fn reports_fallback_model() {
+    let not_found = r#"{"error": {"code": 404, "message": "Model not found.", "status": "NOT_FOUND"}}"#;
+    let base_url = serve(vec![Reply::json("404 Not Found", not_found), Reply::sse(&[&chunk("Hello")])]).await;
+    let client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(base_url)
+        .with_model("gemini-2.5-pro")
+        .with_model_fallbacks(vec!["gemini-2.5-flash"]);
+
+    let (stream, model) = client.generate_content_stream_with_model(&RequestBuilder::new().user("hello").build()).await.unwrap();
+    let texts: Vec<String> = stream.map(|chunk| chunk.unwrap().text()).collect().await;
+    assert_eq!(model, "gemini-2.5-flash");
+    assert_eq!(texts, ["Hello"]);
+}
@@ -0,0 +1,64 @@
+use std::sync::{Arc, Mutex};
+use gemini_code_assist_adapter::{CodeAssistClient, RequestBuilder};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const ANSWER: &str = r#"{"response": {"candidates": [{"content": {"role": "model", "parts": [{"text": "hi"}]}}]}}"#;
+
+/// Answers one request and returns its head (request line and headers).
+async fn serve_capturing_head() -> (String, tokio::task::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = vec![0; 64 * 1024];
+        let head_end = loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end;
+            }
+        };
+        let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+        let length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length: "))
+            .map_or(0, |length| length.trim().parse().unwrap());
+        while request.len() < head_end + 4 + length {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            ANSWER.len(),
+            ANSWER
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+        head
+    });
+    (format!("http://{}/v1internal", addr), handle)
+}
+
+#[tokio::test]
+async fn progress_upload_keeps_content_length() {
+    let (base_url, head) = serve_capturing_head().await;
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let recorder = reports.clone();
+    let client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(base_url)
+        .with_upload_progress(Arc::new(move |sent, total| recorder.lock().unwrap().push((sent, total))));
+
+    // Large enough to be sent in several chunks
+    let request = RequestBuilder::new().user("x".repeat(200 * 1024)).build();
+    assert_eq!(client.generate_content(&request).await.unwrap().text(), "hi");
+
+    let head = head.await.unwrap();
+    assert!(!head.contains("transfer-encoding: chunked"), "{}", head);
+    let reports = reports.lock().unwrap();
+    let (sent, total) = *reports.last().unwrap();
+    assert!(reports.len() > 1);
+    assert_eq!(Some(sent), total);
+    assert!(head.contains(&format!("content-length: {}", sent)), "{}", head);
+}
@@ -0,0 +1,41 @@
+use gemini_code_assist_adapter::error::AdapterError;
+use gemini_code_assist_adapter::CodeAssistClient;
+
+mod common;
+use common::{serve, Reply};
+
+const HANDSHAKE: &str = r#"{"cloudaicompanionProject": "project", "currentTier": {"id": "free-tier"}}"#;
+const TOKENS: &str = r#"{"totalTokens": 1}"#;
+
+async fn verify(replies: Vec<Reply>) -> Result<(), AdapterError> {
+    let client = CodeAssistClient::new("token".into(), "project".into())
+        .with_base_url(serve(replies).await)
+        .with_model("gemini-1.0-retired");
+    client.verify().await
+}
+
+#[tokio::test]
+async fn passes_with_project_and_model() {
+    assert!(verify(vec![Reply::json("200 OK", HANDSHAKE), Reply::json("200 OK", TOKENS)]).await.is_ok());
+}
+
+#[tokio::test]
+async fn inaccessible_project() {
+    let denied = r#"{"error": {"code": 403, "message": "Permission denied on resource project project.", "status": "PERMISSION_DENIED"}}"#;
+    let result = verify(vec![Reply::json("403 Forbidden", denied)]).await;
+    assert!(matches!(result, Err(AdapterError::ProjectNotFound(ref project)) if project == "project"), "{:?}", result);
+}
+
+#[tokio::test]
+async fn unknown_model() {
+    let not_found = r#"{"error": {"code": 404, "message": "Requested entity was not found: models/gemini-1.0-retired", "status": "NOT_FOUND"}}"#;
+    let result = verify(vec![Reply::json("200 OK", HANDSHAKE), Reply::json("404 Not Found", not_found)]).await;
+    assert!(matches!(result, Err(AdapterError::ModelNotFound(ref model)) if model == "gemini-1.0-retired"), "{:?}", result);
+}
+
+#[tokio::test]
+async fn missing_role_is_not_a_missing_project() {
+    let denied = r#"{"error": {"code": 403, "message": "The caller does not have permission", "status": "PERMISSION_DENIED"}}"#;
+    let result = verify(vec![Reply::json("200 OK", HANDSHAKE), Reply::json("403 Forbidden", denied)]).await;
+    assert!(matches!(result, Err(AdapterError::ApiError { code: 403, .. })), "{:?}", result);
+}
@@ -991,14 +991,17 @@ impl Chat {
                             .with_model(model_picker.selected.to_string());
 
                     // Handshake
-                    match client.load_code_assist().await {
-                        Ok(effective_proj) => {
-                            client.set_project_id(effective_proj);
+                    match client.load_code_assist_full().await {
+                        Ok(handshake) => {
+                            if let Some(tier) = handshake.onboarding_tier() {
+                                client.set_tier_id(tier.id.clone());
+                            }
+                            client.set_project_id(handshake.project_id);
                         }
                         Err(e) => log::warn!("Code Assist handshake failed: {e}"),
                     }
 
-                    if let Err(e) = client.onboard_user().await {
+                    if let Err(e) = client.onboard_user_blocking().await {
                         log::warn!("Code Assist onboarding warning: {e}");
                     }
 
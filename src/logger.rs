@@ -1,21 +1,61 @@
-use log::{Level, Metadata, Record, SetLoggerError};
-use std::sync::{mpsc, Mutex, OnceLock};
+use log::{Level, LevelFilter, Metadata, Record, SetLoggerError};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
 
+/// How many captured events are kept by default until the UI picks them up.
+pub const DEFAULT_CAPACITY: usize = 500;
+
+#[derive(Clone, Debug)]
 pub struct LogEvent {
     pub level: Level,
     pub message: String,
+    /// Module the event was logged from, e.g. `gemini_code_assist_adapter::auth`.
+    pub module_path: Option<String>,
+    pub line: Option<u32>,
+    pub timestamp: SystemTime,
+}
+
+/// Keeps the last `capacity` events, dropping the oldest ones, so logs don't pile up
+/// while the UI isn't polling (e.g. minimized window).
+struct LogBuffer {
+    events: VecDeque<LogEvent>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    fn push(&mut self, event: LogEvent) {
+        if self.events.len() >= self.capacity {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
 }
 
-static LOG_RECEIVER: OnceLock<Mutex<mpsc::Receiver<LogEvent>>> = OnceLock::new();
+static LOG_BUFFER: OnceLock<Mutex<LogBuffer>> = OnceLock::new();
+
+type Subscriber = Box<dyn Fn(&LogEvent) + Send>;
+
+/// Everyone observing captured events. The buffer behind [`pop_logs`] is one of them.
+static SUBSCRIBERS: Mutex<Vec<Subscriber>> = Mutex::new(Vec::new());
+
+/// Registers a callback that receives every captured event, e.g. to write them to a file.
+/// It runs on the logging thread, so it should be quick and must not log itself.
+pub fn subscribe(subscriber: impl Fn(&LogEvent) + Send + 'static) {
+    if let Ok(mut subscribers) = SUBSCRIBERS.lock() {
+        subscribers.push(Box::new(subscriber));
+    }
+}
 
 struct GlobalLogger {
     inner: env_logger::Logger,
-    sender: Mutex<mpsc::Sender<LogEvent>>,
+    /// Most verbose level captured for the UI.
+    capture: LevelFilter,
 }
 
 impl log::Log for GlobalLogger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        self.inner.enabled(metadata)
+        self.inner.enabled(metadata) || metadata.level() <= self.capture
     }
 
     fn log(&self, record: &Record) {
@@ -24,17 +64,22 @@ impl log::Log for GlobalLogger {
             self.inner.log(record);
         }
 
-        // Capture Warn and Error logs for the UI
-        if record.level() <= Level::Warn {
+        // Capture logs up to the configured level (Warn and Error by default) for the UI
+        if record.level() <= self.capture {
             // We format the message immediately.
             let msg = format!("{}", record.args());
 
-            // Send to the channel
-            if let Ok(sender) = self.sender.lock() {
-                let _ = sender.send(LogEvent {
-                    level: record.level(),
-                    message: msg,
-                });
+            let event = LogEvent {
+                level: record.level(),
+                message: msg,
+                module_path: record.module_path().map(str::to_string),
+                line: record.line(),
+                timestamp: SystemTime::now(),
+            };
+            if let Ok(subscribers) = SUBSCRIBERS.lock() {
+                for subscriber in subscribers.iter() {
+                    subscriber(&event);
+                }
             }
         }
     }
@@ -44,32 +89,68 @@ impl log::Log for GlobalLogger {
     }
 }
 
-pub fn init() -> Result<(), SetLoggerError> {
-    let (tx, rx) = mpsc::channel();
+/// Captures every event up to `capture` for the UI (usually `LevelFilter::Warn`; `Debug` shows
+/// the request envelopes in-app), keeping up to `capacity` uncollected events
+/// (e.g. [`DEFAULT_CAPACITY`]).
+pub fn init_with(capacity: usize, capture: LevelFilter) -> Result<(), SetLoggerError> {
+    let buffer = LogBuffer {
+        events: VecDeque::with_capacity(capacity),
+        capacity: capacity.max(1),
+    };
 
-    // Store the receiver globally so the UI can access it later
-    if LOG_RECEIVER.set(Mutex::new(rx)).is_err() {
+    // Store the buffer globally so the UI can access it later
+    if LOG_BUFFER.set(Mutex::new(buffer)).is_err() {
         eprintln!("Logger already initialized");
         return Ok(());
     }
+    subscribe(|event| {
+        if let Some(Ok(mut buffer)) = LOG_BUFFER.get().map(|b| b.lock()) {
+            buffer.push(event.clone());
+        }
+    });
 
     let logger = GlobalLogger {
         inner: env_logger::Builder::from_default_env().build(),
-        sender: Mutex::new(tx),
+        capture,
     };
 
-    log::set_max_level(logger.inner.filter());
+    log::set_max_level(logger.inner.filter().max(capture));
     log::set_boxed_logger(Box::new(logger))
 }
 
 pub fn pop_logs() -> Vec<LogEvent> {
-    let mut logs = Vec::new();
-    if let Some(rx_mutex) = LOG_RECEIVER.get() {
-        if let Ok(rx) = rx_mutex.lock() {
-            while let Ok(log) = rx.try_recv() {
-                logs.push(log);
-            }
+    match LOG_BUFFER.get().map(|b| b.lock()) {
+        Some(Ok(mut buffer)) => buffer.events.drain(..).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(n: usize) -> LogEvent {
+        LogEvent {
+            level: Level::Warn,
+            message: n.to_string(),
+            module_path: None,
+            line: None,
+            timestamp: SystemTime::now(),
         }
     }
-    logs
+
+    #[test]
+    fn buffer_keeps_only_the_newest_events() {
+        let mut buffer = LogBuffer {
+            events: VecDeque::new(),
+            capacity: DEFAULT_CAPACITY,
+        };
+        for n in 0..1000 {
+            buffer.push(event(n));
+        }
+
+        assert_eq!(buffer.events.len(), 500);
+        assert_eq!(buffer.events.front().unwrap().message, "500");
+        assert_eq!(buffer.events.back().unwrap().message, "999");
+    }
 }
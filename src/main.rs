@@ -44,9 +44,26 @@ fn load_icon() -> egui::IconData {
     }
 }
 
+/// Captured log events kept until the UI polls them, overridable via `GEMINI_GUI_LOG_CAPACITY`.
+fn log_capacity() -> usize {
+    std::env::var("GEMINI_GUI_LOG_CAPACITY")
+        .ok()
+        .and_then(|capacity| capacity.parse().ok())
+        .unwrap_or(logger::DEFAULT_CAPACITY)
+}
+
+/// Most verbose level captured for the log window, overridable via `GEMINI_GUI_LOG_LEVEL`
+/// (e.g. "debug"). Warnings and errors by default.
+fn log_capture_level() -> log::LevelFilter {
+    std::env::var("GEMINI_GUI_LOG_LEVEL")
+        .ok()
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(log::LevelFilter::Warn)
+}
+
 #[tokio::main]
 async fn main() {
-    logger::init().expect("failed to initialize logger");
+    logger::init_with(log_capacity(), log_capture_level()).expect("failed to initialize logger");
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_icon(load_icon()),
         ..Default::default()
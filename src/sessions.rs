@@ -1,7 +1,7 @@
 use crate::{
     chat::{Chat, ChatAction, ChatExportFormat},
     file_handler::Attachment,
-    widgets::{ModelPicker, RequestInfoType, Settings},
+    widgets::{LogView, ModelPicker, RequestInfoType, Settings},
 };
 use eframe::egui::{self, vec2, Color32, CornerRadius, Frame, Layout, Stroke};
 use egui_commonmark::CommonMarkCache;
@@ -84,6 +84,10 @@ pub struct Sessions {
     pub settings: Settings,
     #[serde(default = "default_true")]
     left_panel_visible: bool,
+    #[serde(skip)]
+    logs_open: bool,
+    #[serde(skip)]
+    log_view: LogView,
 }
 
 fn default_true() -> bool {
@@ -162,6 +166,8 @@ impl Default for Sessions {
             settings_open: false,
             settings: Settings::default(),
             left_panel_visible: true,
+            logs_open: false,
+            log_view: LogView::default(),
         }
     }
 }
@@ -317,17 +323,18 @@ impl Sessions {
         #[cfg(not(feature = "tts"))]
         let mut request_repaint = false;
 
-        // Poll logs and show them as toasts
+        // Poll logs into the log window, warnings and errors are shown as toasts as well
         for log in crate::logger::pop_logs() {
             match log.level {
                 log::Level::Error => {
-                    self.toasts.add(Toast::error(log.message));
+                    self.toasts.add(Toast::error(log.message.clone()));
                 }
                 log::Level::Warn => {
-                    self.toasts.add(Toast::warning(log.message));
+                    self.toasts.add(Toast::warning(log.message.clone()));
                 }
-                _ => {} // Info/Debug/Trace are ignored by the logger for the channel now
+                _ => {}
             }
+            self.log_view.push(log);
             request_repaint = true;
         }
 
@@ -378,6 +385,9 @@ impl Sessions {
                         }
                     }
 
+                    ui.toggle_value(&mut self.logs_open, "📜")
+                        .on_hover_text("Logs");
+
                     if let Some(chat) = self.chats.get(self.selected_chat) {
                         if let Some(count) = chat.token_count {
                             ui.label(format!("{} tokens", count))
@@ -400,6 +410,11 @@ impl Sessions {
                 });
         }
 
+        egui::Window::new("Logs")
+            .open(&mut self.logs_open)
+            .default_size([720.0, 360.0])
+            .show(ctx, |ui| self.log_view.show(ui));
+
         if request_repaint {
             ctx.request_repaint();
         }
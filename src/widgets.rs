@@ -832,3 +832,98 @@ pub fn thinking_icon(
         }
     }
 }
+
+/// How many captured log events the log window keeps.
+const LOG_VIEW_CAPACITY: usize = 1000;
+
+/// Captured log events as a filterable table, see [`crate::logger::pop_logs`].
+pub struct LogView {
+    events: std::collections::VecDeque<crate::logger::LogEvent>,
+    /// Most verbose level shown.
+    max_level: log::LevelFilter,
+    /// Case-insensitive text the message or module path must contain.
+    filter: String,
+}
+
+impl Default for LogView {
+    fn default() -> Self {
+        Self {
+            events: std::collections::VecDeque::with_capacity(LOG_VIEW_CAPACITY),
+            max_level: log::LevelFilter::Trace,
+            filter: String::new(),
+        }
+    }
+}
+
+impl LogView {
+    pub fn push(&mut self, event: crate::logger::LogEvent) {
+        if self.events.len() >= LOG_VIEW_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    fn level_color(ui: &egui::Ui, level: log::Level) -> egui::Color32 {
+        match level {
+            log::Level::Error => ui.visuals().error_fg_color,
+            log::Level::Warn => ui.visuals().warn_fg_color,
+            log::Level::Info => ui.visuals().text_color(),
+            log::Level::Debug | log::Level::Trace => ui.visuals().weak_text_color(),
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Level")
+                .selected_text(self.max_level.as_str())
+                .show_ui(ui, |ui| {
+                    for level in log::LevelFilter::iter().skip(1) {
+                        ui.selectable_value(&mut self.max_level, level, level.as_str());
+                    }
+                });
+            ui.add(egui::TextEdit::singleline(&mut self.filter).hint_text("Filter"));
+            if ui.button("🗑 Clear").clicked() {
+                self.events.clear();
+            }
+        });
+        ui.separator();
+
+        let filter = self.filter.to_lowercase();
+        let matches = |event: &&crate::logger::LogEvent| {
+            event.level <= self.max_level
+                && (filter.is_empty()
+                    || event.message.to_lowercase().contains(&filter)
+                    || event
+                        .module_path
+                        .as_deref()
+                        .is_some_and(|module| module.to_lowercase().contains(&filter)))
+        };
+
+        egui::ScrollArea::both()
+            .auto_shrink(false)
+            .stick_to_bottom(true)
+            .show(ui, |ui| {
+                egui::Grid::new("log_view_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for event in self.events.iter().filter(matches) {
+                            let time: chrono::DateTime<Local> = event.timestamp.into();
+                            ui.label(time.format("%H:%M:%S%.3f").to_string());
+                            ui.colored_label(
+                                Self::level_color(ui, event.level),
+                                event.level.as_str(),
+                            );
+                            let source = match (&event.module_path, event.line) {
+                                (Some(module), Some(line)) => format!("{module}:{line}"),
+                                (Some(module), None) => module.clone(),
+                                (None, _) => String::new(),
+                            };
+                            ui.weak(source);
+                            ui.label(&event.message);
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}